@@ -0,0 +1,77 @@
+//! Building of SQL at runtime, for queries whose text depends on their
+//! arguments, such as list parameters. This is used by the generated code,
+//! and is not needed otherwise.
+
+/// The default maximum number of host parameters in an SQLite statement,
+/// before SQLite 3.32.0. Lists that are longer are split into several
+/// statements, for queries that give the same result that way.
+pub const SQLITE_MAX_VARIABLES: usize = 999;
+
+/// SQL text along with the parameters bound to its placeholders, which are
+/// numbered in order, e.g. `?1, ?2` for SQLite or `$1, $2` for PostgreSQL.
+pub struct SqlBuilder<'a, P: ?Sized> {
+    text: String,
+    params: Vec<&'a P>,
+    prefix: char,
+    count: usize,
+}
+
+impl<'a, P: ?Sized> SqlBuilder<'a, P> {
+    /// A builder for placeholders starting with `prefix`, i.e. `?` or `$`.
+    pub fn new(prefix: char) -> Self {
+        Self {
+            text: String::new(),
+            params: vec![],
+            prefix,
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, sql: &str) {
+        self.text.push_str(sql);
+    }
+
+    /// Adds a placeholder for the parameter.
+    pub fn bind(&mut self, param: &'a P) {
+        self.placeholders(1);
+        self.params.push(param);
+    }
+
+    /// Adds comma-separated placeholders for the parameters of a list.
+    pub fn bind_list(&mut self, params: impl IntoIterator<Item = &'a P>) {
+        for (idx, param) in params.into_iter().enumerate() {
+            if idx > 0 {
+                self.text.push_str(", ");
+            }
+            self.bind(param);
+        }
+    }
+
+    /// Adds comma-separated placeholders without binding parameters to them,
+    /// for preparing a statement that is executed later.
+    pub fn placeholders(&mut self, count: usize) {
+        for idx in 0..count {
+            if idx > 0 {
+                self.text.push_str(", ");
+            }
+            self.count += 1;
+            self.text.push(self.prefix);
+            self.text.push_str(&self.count.to_string());
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn params(&self) -> &[&'a P] {
+        &self.params
+    }
+}
+
+/// Splits a list into chunks of at most `size` items. An empty list gives a
+/// single empty chunk, so that a query using it is still executed once.
+pub fn chunks<T>(list: &[T], size: usize) -> impl Iterator<Item = &[T]> {
+    let empty = if list.is_empty() { Some(list) } else { None };
+    list.chunks(size.max(1)).chain(empty)
+}
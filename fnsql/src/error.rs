@@ -0,0 +1,65 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Errors of generated methods that check more than the database driver does,
+/// wrapping the errors of the driver, i.e. `rusqlite::Error` or
+/// `postgres::Error`.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// An error of the database driver.
+    Driver(E),
+    /// None of the optional columns of an `update` query were given.
+    NoAssignments,
+    /// A query declared to return a single row returned none.
+    NoRows,
+    /// A query declared to return at most one row returned more.
+    MultipleRows,
+    /// A query with `expect_rows` affected a number of rows out of the expected
+    /// range.
+    AffectedRows {
+        count: u64,
+        expected: RangeInclusive<u64>,
+    },
+    /// The payload of a notification on a `channel` could not be encoded or
+    /// decoded.
+    Payload(Box<dyn std::error::Error + Send + Sync>),
+    /// A `FromRow` implementation failed with an error of its own, rather than
+    /// one of the database driver.
+    Row(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(err: E) -> Self {
+        Error::Driver(err)
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Driver(err) => err.fmt(f),
+            Error::NoAssignments => write!(f, "no columns to update"),
+            Error::NoRows => write!(f, "the query returned no rows"),
+            Error::MultipleRows => write!(f, "the query returned more than one row"),
+            Error::AffectedRows { count, expected } if expected.start() == expected.end() => {
+                write!(f, "the query affected {} rows instead of {}", count, expected.start())
+            }
+            Error::AffectedRows { count, expected } => {
+                write!(f, "the query affected {} rows instead of {}..={}", count,
+                    expected.start(), expected.end())
+            }
+            Error::Payload(err) => write!(f, "invalid notification payload: {}", err),
+            Error::Row(err) => write!(f, "invalid row: {}", err),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for Error<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Driver(err) => Some(err),
+            Error::Payload(err) | Error::Row(err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
@@ -2,6 +2,31 @@ extern crate fnsql_macro;
 
 // Re-export macro
 pub use fnsql_macro::fnsql;
+pub use fnsql_macro::fnsql_file;
+pub use fnsql_macro::FromRow;
 
+pub mod builder;
+mod error;
 #[cfg(feature = "with-postgres")]
 pub mod postgres;
+pub mod rows;
+
+pub use error::Error;
+
+/// Conversion of a database row into a user type, for queries declared with a
+/// type as their output, e.g. `-> [Pet]`.
+///
+/// `R` is the row type of the backend, i.e. `rusqlite::Row<'_>` or
+/// `postgres::Row`. It is usually implemented via `#[derive(fnsql::FromRow)]`.
+///
+/// `Error` is usually the error of the driver, which the generated methods
+/// return as is. Any other error is returned as `Error::Row` by `postgres`
+/// queries, and as `rusqlite::Error::UserFunctionError` by `rusqlite`
+/// queries, whose row callbacks can only fail with a `rusqlite::Error`. That
+/// variant needs the `functions` feature of `rusqlite`, which the crate using
+/// `FromRow` outputs with `rusqlite` needs to enable.
+pub trait FromRow<R>: Sized {
+    type Error: Into<Box<dyn std::error::Error + Send + Sync>>;
+
+    fn from_row(row: &R) -> Result<Self, Self::Error>;
+}
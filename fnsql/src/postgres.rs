@@ -4,6 +4,8 @@
 //!
 //! By default arguments subsitution uses $1 and $2 instead of ':name', unless
 //! you provide the attribute 'named'.
+//! The translation of ':name' to '$N' skips string literals, dollar-quoted
+//! bodies, comments and '::' casts.
 //!
 //!
 //! ## Cache for prepared statement
@@ -105,8 +107,8 @@ pub fn testing_docker_down(compose_yaml: &str) -> Result<(), std::io::Error> {
     }, compose_yaml)
 }
 
-pub static DOCKER_COMPOSE: &'static str = include_str!("postgres/docker-compose.yml");
-pub static SQL_SETUP: &'static str = include_str!("postgres/sql_setup.sh");
+pub static DOCKER_COMPOSE: &str = include_str!("postgres/docker-compose.yml");
+pub static SQL_SETUP: &str = include_str!("postgres/sql_setup.sh");
 
 #[macro_export]
 macro_rules! fnsql_define_postgres_test_handlers {
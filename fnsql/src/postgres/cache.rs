@@ -3,6 +3,7 @@ use postgres::{Statement, types::Type, Error, GenericClient};
 
 type Key = (Cow<'static, str>, Cow<'static, [Type]>);
 
+#[derive(Default)]
 pub struct Cache {
     map: HashMap<Key, Statement>,
 }
@@ -23,7 +24,7 @@ impl Cache {
         let cow_query = Cow::Borrowed(query);
 
         match self.map.get(&(cow_query, cow_types)) {
-            Some(stmt) => return Ok(stmt.clone()),
+            Some(stmt) => Ok(stmt.clone()),
             None => {
                 let stmt = client.prepare_typed(query, types)?;
                 self.map.insert((Cow::Owned(query.to_owned()),
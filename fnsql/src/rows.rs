@@ -0,0 +1,63 @@
+//! Checks of the number of rows returned by queries that are declared to
+//! return a single row, e.g. `-> (i32, String)`, and of the number of rows
+//! affected by queries with `expect_rows`, and conversion of the errors of
+//! `FromRow` implementations. This is used by the generated code, and is not
+//! needed otherwise.
+
+use std::error::Error as StdError;
+use std::ops::RangeInclusive;
+
+use crate::Error;
+
+/// The only row, failing with `Error::NoRows` or `Error::MultipleRows`
+/// otherwise.
+pub fn one<T, E, R>(rows: impl IntoIterator<Item = Result<T, R>>) -> Result<T, Error<E>>
+where
+    R: Into<Error<E>>,
+{
+    opt(rows)?.ok_or(Error::NoRows)
+}
+
+/// The row if there is one, failing with `Error::MultipleRows` if there are
+/// more. Rows after the second are not fetched.
+pub fn opt<T, E, R>(rows: impl IntoIterator<Item = Result<T, R>>) -> Result<Option<T>, Error<E>>
+where
+    R: Into<Error<E>>,
+{
+    let mut rows = rows.into_iter();
+    let first = rows.next().transpose().map_err(R::into)?;
+    match rows.next() {
+        None => Ok(first),
+        Some(Err(err)) => Err(err.into()),
+        Some(Ok(_)) => Err(Error::MultipleRows),
+    }
+}
+
+/// Fails with `Error::AffectedRows` if `count` is out of the `expected` range.
+pub fn expect_affected<E>(count: u64, expected: RangeInclusive<u64>) -> Result<(), Error<E>> {
+    if expected.contains(&count) {
+        Ok(())
+    } else {
+        Err(Error::AffectedRows { count, expected })
+    }
+}
+
+/// The error of a `FromRow` implementation if it is the error of the driver
+/// `E`, or the error of its own otherwise.
+pub fn driver_error<E, R>(err: R) -> Result<E, Box<dyn StdError + Send + Sync>>
+where
+    E: StdError + 'static,
+    R: Into<Box<dyn StdError + Send + Sync>>,
+{
+    err.into().downcast().map(|err| *err)
+}
+
+/// The error of a `FromRow` implementation as `Error::Driver` if it is the
+/// error of the driver `E`, or as `Error::Row` otherwise.
+pub fn row_error<E, R>(err: R) -> Error<E>
+where
+    E: StdError + 'static,
+    R: Into<Box<dyn StdError + Send + Sync>>,
+{
+    driver_error(err).map_or_else(Error::Row, Error::Driver)
+}
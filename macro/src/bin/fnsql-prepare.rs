@@ -0,0 +1,245 @@
+//! Prepares the `postgres` queries of all `fnsql!` and `fnsql_file!` blocks in
+//! a crate against a database, and records their parameter and column types
+//! under `.fnsql/`, to be checked at compile time with the `check-postgres`
+//! feature.
+//!
+//! Usage: `fnsql-prepare <connection string> [crate directory]`
+//!
+//! Each block is handled in its own transaction, which is rolled back at the
+//! end. The queries that others depend on via `test(with=[..])`, or that are
+//! marked with `schema`, are executed first so that the rest can be prepared.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use postgres::{Client, NoTls, Transaction};
+use syn::visit::Visit;
+
+#[allow(dead_code)]
+#[path = "../cache.rs"]
+mod cache;
+#[allow(dead_code)]
+#[path = "../query.rs"]
+mod query;
+#[allow(dead_code)]
+#[path = "../sql.rs"]
+mod sql;
+#[path = "../sql_file.rs"]
+mod sql_file;
+
+use query::{Kind, Queries, Query};
+
+struct Blocks<'a> {
+    crate_dir: &'a Path,
+    list: Vec<Vec<Query>>,
+    errors: Vec<syn::Error>,
+}
+
+impl<'a, 'ast> Visit<'ast> for Blocks<'a> {
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        let name = match mac.path.segments.last() {
+            Some(segment) => segment.ident.to_string(),
+            None => return,
+        };
+
+        let result = match name.as_str() {
+            "fnsql" => mac.parse_body::<Queries>().map(|queries| queries.list),
+            "fnsql_file" => mac.parse_body::<syn::LitStr>()
+                .and_then(|path| sql_file::load(self.crate_dir, &path)),
+            _ => return,
+        };
+
+        match result {
+            Ok(queries) => self.list.push(queries),
+            Err(err) => self.errors.push(err),
+        }
+    }
+}
+
+fn source_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            source_files(&path, files)?;
+        } else if path.extension() == Some(OsStr::new("rs")) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn is_postgres(query: &Query) -> bool {
+    matches!(query.kind, Kind::PostgreSQL)
+}
+
+/// Executes the schema queries of a block, dependencies first. Failures are
+/// only reported, as the database may already contain the schema.
+fn apply_schema(tx: &mut Transaction, queries: &[Query]) {
+    let depends: HashSet<String> = queries
+        .iter()
+        .filter(|query| is_postgres(query))
+        .flat_map(|query| query.test.iter().flatten().cloned())
+        .collect();
+
+    let mut applied = HashSet::new();
+    for query in queries.iter().filter(|query| is_postgres(query)) {
+        if query.schema || query.script || depends.contains(&query.name.to_string()) {
+            apply(tx, queries, query, &mut applied);
+        }
+    }
+}
+
+fn apply(tx: &mut Transaction, queries: &[Query], query: &Query,
+    applied: &mut HashSet<String>)
+{
+    if !applied.insert(query.name.to_string()) {
+        return;
+    }
+
+    for depend in query.test.iter().flatten() {
+        let found = queries.iter().find(|x| is_postgres(x) && x.name == depend);
+        if let Some(depend) = found {
+            apply(tx, queries, depend, applied);
+        }
+    }
+
+    if query.unchecked || !query.params.is_empty() {
+        return;
+    }
+
+    let result = tx.transaction().and_then(|mut savepoint| {
+        savepoint.batch_execute(&query.postgres_sql())?;
+        savepoint.commit()
+    });
+
+    if let Err(err) = result {
+        eprintln!("warning: {}: {}", query.name, err);
+    }
+}
+
+/// Prepares the queries of a block, adding the contents of their cache files to
+/// `entries`, by file name.
+fn prepare_block(client: &mut Client, queries: &[Query],
+    entries: &mut BTreeMap<String, String>) -> Result<(), String>
+{
+    let mut tx = client.transaction().map_err(|err| err.to_string())?;
+
+    apply_schema(&mut tx, queries);
+
+    for query in queries.iter().filter(|query| is_postgres(query)) {
+        // Scripts cannot be prepared, as they have several statements, and
+        // channels have no SQL.
+        if query.unchecked || query.script || query.channel.is_some() {
+            continue;
+        }
+
+        let sql = query.described_sql();
+        let stmt = tx.prepare(&sql).map_err(|err| format!("{}: {}", query.name, err))?;
+
+        let params: Vec<_> = stmt.params().iter().map(|ttype| ttype.name()).collect();
+        let columns: Vec<_> = stmt
+            .columns()
+            .iter()
+            .map(|column| serde_json::json!({
+                "name": column.name(),
+                "type": column.type_().name(),
+            }))
+            .collect();
+
+        let json = serde_json::json!({
+            "query": sql,
+            "params": params,
+            "columns": columns,
+        });
+
+        let contents = serde_json::to_string_pretty(&json).unwrap() + "\n";
+        entries.insert(cache::file_name(&sql), contents);
+    }
+
+    Ok(())
+}
+
+fn is_cache_file(name: &str) -> bool {
+    name.starts_with("query-") && name.ends_with(".json")
+}
+
+/// Writes the cache files, and only then removes the stale ones, so that a
+/// failed run leaves the existing cache intact.
+fn write_cache(dir: &Path, entries: &BTreeMap<String, String>) -> Result<(), String> {
+    if !dir.exists() {
+        std::fs::create_dir(dir).map_err(|err| err.to_string())?;
+    }
+
+    for (name, contents) in entries {
+        let path = dir.join(name);
+        std::fs::write(&path, contents)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+
+    for entry in std::fs::read_dir(dir).map_err(|err| err.to_string())? {
+        let path = entry.map_err(|err| err.to_string())?.path();
+        let name = match path.file_name().and_then(OsStr::to_str) {
+            Some(name) => name,
+            None => continue,
+        };
+        if is_cache_file(name) && !entries.contains_key(name) {
+            std::fs::remove_file(&path)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn run(conn_str: &str, crate_dir: &Path) -> Result<(), String> {
+    let mut files = vec![];
+    source_files(&crate_dir.join("src"), &mut files).map_err(|err| err.to_string())?;
+    files.sort();
+
+    let mut blocks = vec![];
+    for file in &files {
+        let source = std::fs::read_to_string(file).map_err(|err| err.to_string())?;
+        let syntax = syn::parse_file(&source)
+            .map_err(|err| format!("{}: {}", file.display(), err))?;
+
+        let mut visitor = Blocks { crate_dir, list: vec![], errors: vec![] };
+        visitor.visit_file(&syntax);
+        if let Some(err) = visitor.errors.first() {
+            return Err(format!("{}: {}", file.display(), err));
+        }
+        blocks.extend(visitor.list);
+    }
+
+    let mut client = Client::connect(conn_str, NoTls).map_err(|err| err.to_string())?;
+
+    let mut entries = BTreeMap::new();
+    for block in &blocks {
+        prepare_block(&mut client, block, &mut entries)?;
+    }
+
+    let dir = crate_dir.join(cache::DIR);
+    write_cache(&dir, &entries)?;
+
+    println!("prepared {} queries into {}", entries.len(), dir.display());
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (conn_str, crate_dir) = match args.as_slice() {
+        [conn_str] => (conn_str, "."),
+        [conn_str, crate_dir] => (conn_str, crate_dir.as_str()),
+        _ => {
+            eprintln!("usage: fnsql-prepare <connection string> [crate directory]");
+            exit(2);
+        }
+    };
+
+    if let Err(err) = run(conn_str, Path::new(crate_dir)) {
+        eprintln!("error: {}", err);
+        exit(1);
+    }
+}
@@ -0,0 +1,36 @@
+//! Location of the offline metadata cache for `postgres` queries, which
+//! `fnsql-prepare` writes and the `check-postgres` feature reads.
+//!
+//! Each query is stored in `.fnsql/query-<hash>.json` under the crate root,
+//! where the hash is taken over the SQL as sent to the server:
+//!
+//! ```json
+//! {
+//!   "query": "SELECT id FROM pet WHERE name = $1",
+//!   "params": ["text"],
+//!   "columns": [{ "name": "id", "type": "int4" }]
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+pub(crate) const DIR: &str = ".fnsql";
+
+/// 64-bit FNV-1a, which is stable across compiler versions, unlike
+/// `std::collections::hash_map::DefaultHasher`.
+fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub(crate) fn file_name(sql: &str) -> String {
+    format!("query-{:016x}.json", fnv1a(sql.as_bytes()))
+}
+
+pub(crate) fn path(crate_dir: &Path, sql: &str) -> PathBuf {
+    crate_dir.join(DIR).join(file_name(sql))
+}
@@ -0,0 +1,118 @@
+use proc_macro2::TokenStream as Tokens;
+use quote::quote;
+use syn::{punctuated::Punctuated, Data, DeriveInput, Fields, Ident, LitInt, LitStr, Token};
+
+pub(crate) fn derive(input: &DeriveInput) -> syn::Result<Tokens> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                input,
+                "FromRow can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut impls = vec![];
+
+    for backend in backends(input)? {
+        if backend == "rusqlite" {
+            impls.push(derive_rusqlite(input, fields));
+        } else if backend == "postgres" {
+            impls.push(derive_postgres(input, fields));
+        } else {
+            return Err(syn::Error::new_spanned(
+                backend,
+                "unknown backend, expected `rusqlite` or `postgres`",
+            ));
+        }
+    }
+
+    if impls.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "FromRow requires the backends to implement it for, \
+             e.g. `#[fnsql(rusqlite)]` or `#[fnsql(rusqlite, postgres)]`",
+        ));
+    }
+
+    Ok(quote! { #(#impls)* })
+}
+
+/// The backends listed by the `#[fnsql(...)]` attributes of the struct. They are
+/// given explicitly, as the enabled features of fnsql do not tell which backends
+/// the deriving crate uses.
+fn backends(input: &DeriveInput) -> syn::Result<Vec<Ident>> {
+    let mut backends = vec![];
+
+    for attr in &input.attrs {
+        if attr.path.is_ident("fnsql") {
+            let list = attr.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?;
+            backends.extend(list);
+        }
+    }
+
+    Ok(backends)
+}
+
+/// Builds the struct from `row` using the given getter method, by field name
+/// or by field index.
+fn construct(fields: &Fields, getter: Tokens) -> Tokens {
+    match fields {
+        Fields::Named(fields) => {
+            let list = fields.named.iter().map(|field| {
+                let name = field.ident.as_ref().unwrap();
+                let column = LitStr::new(&name.to_string(), name.span());
+                quote! { #name: row.#getter(#column)? }
+            });
+            quote! { Self { #(#list),* } }
+        }
+        Fields::Unnamed(fields) => {
+            let list = fields.unnamed.iter().enumerate().map(|(i, _)| {
+                let i = LitInt::new(&format!("{}", i), proc_macro2::Span::call_site());
+                quote! { row.#getter(#i)? }
+            });
+            quote! { Self(#(#list),*) }
+        }
+        Fields::Unit => quote! { Self },
+    }
+}
+
+fn derive_rusqlite(input: &DeriveInput, fields: &Fields) -> Tokens {
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    generics.params.insert(0, syn::parse_quote!('fnsql_row));
+    let (impl_generics, _, _) = generics.split_for_impl();
+    let (_, ty_generics, where_clause) = input.generics.split_for_impl();
+    let construct = construct(fields, quote! {get});
+
+    quote! {
+        impl #impl_generics fnsql::FromRow<rusqlite::Row<'fnsql_row>> for #name #ty_generics
+            #where_clause
+        {
+            type Error = rusqlite::Error;
+
+            fn from_row(row: &rusqlite::Row<'fnsql_row>) -> Result<Self, Self::Error> {
+                Ok(#construct)
+            }
+        }
+    }
+}
+
+fn derive_postgres(input: &DeriveInput, fields: &Fields) -> Tokens {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let construct = construct(fields, quote! {try_get});
+
+    quote! {
+        impl #impl_generics fnsql::FromRow<postgres::Row> for #name #ty_generics
+            #where_clause
+        {
+            type Error = postgres::Error;
+
+            fn from_row(row: &postgres::Row) -> Result<Self, Self::Error> {
+                Ok(#construct)
+            }
+        }
+    }
+}
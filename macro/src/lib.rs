@@ -92,6 +92,10 @@
 //! - `fnsql` writes the tests for each of the queries.  - `Arbitrary` is used to
 //!   generate parameter values.
 //! - If testing one query depend on another, you can specify that with `test(with=[..])`.
+//! - Attributes shared by all the queries of a block can be given once at its top,
+//!   e.g. `#![rusqlite, test(with=[create_table_pet])]`. A query's own `test(with=[..])`
+//!   adds to the default dependencies, and `test(with=[])` clears them, as needed by
+//!   `create_table_pet` itself.
 //!
 //! ```text
 //! running 3 tests
@@ -107,262 +111,1466 @@
 //! arbitrary = { version = "1", features = ["derive"] }
 //! ```
 //!
+//! ## Scripts
+//!
+//! With the `script` attribute, a query can hold several statements, such as a table
+//! along with its indexes. They are executed together by `execute_batch_<name>`,
+//! and the query can be a `test(with=[...])` dependency like any other:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[rusqlite, script, test]
+//!     create_table_pet() {
+//!         "CREATE TABLE pet (id INTEGER PRIMARY KEY, name TEXT NOT NULL, data BLOB);
+//!          CREATE INDEX pet_name ON pet (name);"
+//!     }
+//! }
+//! ```
+//!
+//! A script cannot have parameters or outputs. Its statements are part of the schema
+//! that the compile-time validation checks the other queries against.
+//!
+//! ## Multiple backends
+//!
+//! A query can target both `rusqlite` and `postgres` with `#[rusqlite, postgres]`.
+//! Its placeholders are written once as `:name`, and are translated to `$N` for
+//! `postgres`, so the same calling code works with a `rusqlite::Connection` and
+//! with a `postgres::Client`:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[rusqlite, postgres, test(with=[create_table_pet])]
+//!     get_pet_id_data(name: Option<String>) -> [(i32, Option<Vec<u8>>)] {
+//!         "SELECT id, data FROM pet WHERE pet.name = :name"
+//!     }
+//! }
+//! ```
+//!
+//! Where the dialects differ, the body of the query can hold SQL for each backend
+//! instead. The backends of the query are then those of its variants, and the
+//! generated methods are the same as for a query with a single string:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     insert_pet_once(id: i32, name: String) {
+//!         rusqlite: "INSERT OR IGNORE INTO pet (id, name) VALUES (:id, :name)",
+//!         postgres: "INSERT INTO pet (id, name) VALUES (:id, :name) ON CONFLICT DO NOTHING",
+//!     }
+//! }
+//! ```
+//!
+//! ## Single rows
+//!
+//! Besides `-> [...]` for any number of rows, the output of a query can be a single
+//! row, which is fetched by the only method generated for the query:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #![rusqlite, test(with=[create_table_pet])]
+//!
+//!     // `query_scalar_get_pet_count`, for a single column of exactly one row
+//!     get_pet_count() -> i64 {
+//!         "SELECT count(*) FROM pet"
+//!     }
+//!
+//!     // `query_one_get_pet`, for exactly one row
+//!     get_pet(id: i32) -> (String, Option<Vec<u8>>) {
+//!         "SELECT name, data FROM pet WHERE id = :id"
+//!     }
+//!
+//!     // `query_opt_find_pet`, for zero or one row
+//!     find_pet(name: str) -> Option<(id: i32, data: Option<Vec<u8>>)> {
+//!         "SELECT id, data FROM pet WHERE name = :name"
+//!     }
+//! }
+//! ```
+//!
+//! These methods return `fnsql::Error`, which is `fnsql::Error::NoRows` or
+//! `fnsql::Error::MultipleRows` when the number of rows does not match, and otherwise
+//! wraps the error of the backend. A `FromRow` output can only be returned as rows,
+//! e.g. `-> [Pet]`, as a single type such as `-> Pet` is taken to be a column.
+//!
+//! ## Affected rows
+//!
+//! The `expect_rows` attribute checks the number of rows that executing the query
+//! affects, e.g. for an `UPDATE` that is expected to match a single row:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[rusqlite, expect_rows = 1, test(with=[create_table_pet])]
+//!     rename_pet(id: i32, version: i32, name: str) {
+//!         "UPDATE pet SET name = :name, version = version + 1
+//!          WHERE id = :id AND version = :version"
+//!     }
+//! }
+//! ```
+//!
+//! The expected number can also be a range, e.g. `expect_rows = 0..=1`. The
+//! `execute_` and `execute_prepared_` methods and `execute` of the statement types
+//! then return `fnsql::Error`, which is `fnsql::Error::AffectedRows` when the number
+//! is out of range.
+//!
+//! ## Inserts
+//!
+//! For `rusqlite`, the `rowid` attribute generates `execute_rowid_<name>`, which
+//! returns the `last_insert_rowid()` of the inserted row, as an `i64` or as a type
+//! that implements `From<i64>`:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[rusqlite, rowid = PetId, test(with=[create_table_pet])]
+//!     insert_named_pet(name: str) {
+//!         "INSERT INTO pet (name) VALUES (:name)"
+//!     }
+//!
+//!     #[rusqlite, test(with=[create_table_pet])]
+//!     insert_pet_returning(name: str) -> [(id: i32, name: String)] {
+//!         "INSERT INTO pet (name) VALUES (:name) RETURNING id, name"
+//!     }
+//! }
+//! ```
+//!
+//! A query with outputs and a `RETURNING` clause is executed as a query, and so its
+//! `execute_` method returns the rows rather than their number. Like for `postgres`,
+//! the returned row can also be fetched with `query_one_`, given `-> (i32, String)`.
+//!
+//! ## List parameters
+//!
+//! A slice parameter declared with `as list` binds a variable-length list, and is
+//! used as `IN (:ids)` or `NOT IN (:ids)`:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[rusqlite, postgres, test(with=[create_table_pet])]
+//!     get_pet_names(ids: [i64] as list) -> [(String)] {
+//!         "SELECT name FROM pet WHERE id IN (:ids)"
+//!     }
+//! }
+//! ```
+//!
+//! For `postgres`, the list is bound as an array with `= ANY(:ids)`. For `rusqlite`,
+//! the SQL is built at runtime with a placeholder per item, and so `prepare_` and
+//! `prepare_cached_` take the length of the list. The generated `execute_`, `query_`
+//! and `query_row_` split lists that exceed the SQLite limit on placeholders before
+//! SQLite 3.32.0 (999), and execute the query for each part within a savepoint, where
+//! that gives the same result: the list is only used by `IN` conditions that are joined
+//! to the rest of the `WHERE` clause by `AND`, and the query does not combine rows with
+//! `DISTINCT`, `GROUP BY`, `ORDER BY`, `LIMIT`, compound `SELECT`s, window functions or
+//! aggregates. Other queries, e.g. with `WHERE id IN (:ids) OR name = :name`, are
+//! executed with the whole list, which SQLite rejects if it exceeds the limit of its
+//! version.
+//!
+//! ## Optional fragments
+//!
+//! A bracketed part of the SQL that uses an `Option` parameter is included only when
+//! the parameter is `Some`, and then binds its value:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[rusqlite, test(with=[create_table_pet])]
+//!     find_pets(name: Option<String>, min_id: Option<i64>) -> [Pet] {
+//!         "SELECT id, name, data FROM pet WHERE 1 = 1 [AND name = :name] [AND id >= :min_id]"
+//!     }
+//! }
+//! ```
+//!
+//! Only a bracketed part that starts a word counts, so that array subscripts such as
+//! `tags[1]`, and `ARRAY [...]` with or without whitespace, are left as they are. In
+//! such queries, SQLite identifiers are better quoted with `"..."` than with `[...]`.
+//!
+//! The SQL of such a query is built at runtime, so there are no `prepare_` methods
+//! for it. For `rusqlite`, `execute_`, `query_` and `query_row_` are generated, and
+//! for `postgres`, `execute_`, `query_`, `query_one_` and `query_opt_`. The compile-time
+//! validation checks the query with all of its fragments included.
+//!
+//! ## Partial updates
+//!
+//! With the `update` attribute, the assignments of an `UPDATE` that use an `Option`
+//! parameter are only included when it is `Some`, which suits PATCH-style APIs:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[rusqlite, update, test(with=[create_table_pet])]
+//!     update_pet(id: i64, name: Option<String>, data: Option<Option<Vec<u8>>>) {
+//!         "UPDATE pet SET name = :name, data = :data WHERE id = :id"
+//!     }
+//! }
+//! ```
+//!
+//! Like a query with optional fragments, its SQL is built at runtime. The methods
+//! return `fnsql::Error`, which is `fnsql::Error::NoAssignments` when none of the
+//! assignments are given, and otherwise wraps the error of the backend.
+//!
+//! ## Struct parameters
+//!
+//! A parameter can list fields of its struct, which are bound to the placeholders
+//! named after them, so that a row is passed as a whole:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[rusqlite, test(with=[create_table_pet])]
+//!     insert_pet(pet: &Pet { id, name, data }) {
+//!         "INSERT INTO pet (id, name, data) VALUES (:id, :name, :data)"
+//!     }
+//! }
+//! ```
+//!
+//! The generated methods take `pet: &Pet`. For positional `postgres` placeholders,
+//! the fields are numbered in order, here from `$1` to `$3`. As the types of the
+//! fields are not declared, they are not validated at compile time, and the generated
+//! test needs the struct to implement `arbitrary::Arbitrary`.
+//!
+//! ## Bulk inserts
+//!
+//! The `many` attribute generates `insert_many_<name>`, which executes the query for
+//! each item of an iterator, with a statement prepared once:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[rusqlite, postgres, many, test(with=[create_table_pet])]
+//!     insert_new_pet(name: str, data: Option<Vec<u8>>) {
+//!         "INSERT INTO pet (name, data) VALUES (:name, :data)"
+//!     }
+//! }
+//! ```
+//!
+//! An item is a tuple of the parameters, e.g. `("Max", None)`, or the parameter
+//! itself if there is only one, such as a struct parameter. Values can be owned or
+//! borrowed. The loop runs within a savepoint for `rusqlite` and a transaction for
+//! `postgres`, so that either all of the items are inserted or none is, and the total
+//! number of affected rows is returned.
+//!
+//! ## Binary COPY
+//!
+//! For `postgres`, a `COPY` in the binary format can be declared with the types of
+//! its columns, given as parameters for `copy_in` and as outputs for `copy_out`:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[postgres, copy_in, test(with=[create_table_pet])]
+//!     pet_rows(id: i32, name: str, data: Option<Vec<u8>>) {
+//!         "COPY pet (id, name, data) FROM STDIN BINARY"
+//!     }
+//!
+//!     #[postgres, copy_out, test(with=[create_table_pet])]
+//!     pet_names() -> [(id: i32, name: String)] {
+//!         "COPY (SELECT id, name FROM pet ORDER BY id) TO STDOUT (FORMAT binary)"
+//!     }
+//! }
+//! ```
+//!
+//! `copy_in_pet_rows()` returns a `CopyIn_pet_rows` writer, whose `write` takes the
+//! columns of a row and whose `finish` completes the copy. `copy_out_pet_names()`
+//! returns a `CopyOut_pet_names` iterator over the rows. A `copy_in` query needs a
+//! column list, which is matched to the parameters in order. The types of the columns
+//! are found by preparing a `SELECT` of them, which is also what `fnsql-prepare`
+//! records for the compile-time validation.
+//!
+//! ## Notification channels
+//!
+//! For `postgres`, the `channel` attribute declares a `LISTEN`/`NOTIFY` channel with a
+//! typed payload. It is named after the query unless a body gives its name:
+//!
+//! ```rust ignore
+//! fnsql::fnsql! {
+//!     #[postgres, channel = json]
+//!     pet_changed(event: PetEvent);
+//!
+//!     #[postgres, channel]
+//!     pets_cleared(reason: str) {
+//!         "pets-cleared"
+//!     }
+//! }
+//! ```
+//!
+//! This generates `listen_pet_changed()`, `unlisten_pet_changed()` and
+//! `notify_pet_changed(&event)`, along with a `Notifications_pet_changed` wrapper
+//! of an iterator of `Client::notifications()`, which skips the notifications of
+//! other channels and decodes the payloads:
+//!
+//! ```rust ignore
+//! for event in Notifications_pet_changed(client.notifications().blocking_iter()) {
+//!     println!("Pet changed {:?}", event?);
+//! }
+//! ```
+//!
+//! A payload of `channel = json` is converted with `serde_json`, which the crate then
+//! depends on. Otherwise it is plain text, converted with `Display` and `FromStr`.
+//! Payloads that fail to convert give `fnsql::Error::Payload`.
+//!
+//! ## Query modules
+//!
+//! Instead of a trait per query, the queries of a block can be wrapped in a module,
+//! which then holds all the generated items along with a single `Queries` extension
+//! trait. One `use` brings in the methods of all the queries:
+//!
+//! ```rust
+//! fnsql::fnsql! {
+//!     mod pets {
+//!         #![rusqlite, test(with=[create_table_pet])]
+//!
+//!         #[test(with=[])]
+//!         create_table_pet() {
+//!             "CREATE TABLE pet (id INTEGER PRIMARY KEY, name TEXT NOT NULL)"
+//!         }
+//!
+//!         get_pet_names() -> [(String)] {
+//!             "SELECT name FROM pet"
+//!         }
+//!     }
+//! }
+//!
+//! use pets::Queries;
+//! ```
+//!
+//! The module sees the items of its parent module. If it mixes backends, there is a
+//! `RusqliteQueries` trait and a `PostgresQueries` trait instead.
+//!
+//! ## Queries in `.sql` files
+//!
+//! Queries can also be kept in `.sql` files, where each one is introduced by
+//! yesql-style `-- name:` and `-- attrs:` header comments, and loaded with
+//! `fnsql_file!`. This generates the same code as the equivalent `fnsql!` block:
+//!
+//! ```sql
+//! -- name: get_pet_id_data(name: Option<String>) -> [(i32, Option<Vec<u8>>)]
+//! -- attrs: rusqlite, test(with=[create_table_pet])
+//! SELECT id, data FROM pet WHERE pet.name = :name
+//! ```
+//!
+//! ```rust ignore
+//! fnsql::fnsql_file!("queries/pets.sql");
+//! ```
+//!
+//! ## Compile-time validation for `rusqlite`
+//!
+//! With the `check-rusqlite` manifest feature, each `fnsql!` block is also
+//! validated during compilation against an in-memory SQLite database. The
+//! queries that others depend on via `test(with=[..])`, or that are marked with
+//! `schema`, build the database schema. All other `rusqlite` queries are
+//! prepared against it, and the number of returned columns is compared to the
+//! declared outputs. Queries marked with `unchecked` are skipped. The feature
+//! is not part of `all`, and needs to be enabled explicitly.
+//!
+//! The columns of queries whose output is a `FromRow` type, e.g. `-> [Pet]`, are
+//! not validated, for either backend, as the fields of the type are not visible to
+//! `fnsql!`. A missing column fails when converting the rows at runtime.
+//!
+//! ## Compile-time validation for `postgres`
+//!
+//! As a PostgreSQL server is not available in every build environment, `postgres`
+//! queries are validated against an offline cache that is checked into the crate.
+//! The `fnsql-prepare` binary (built with the `prepare-postgres` feature of
+//! `fnsql-macro`) finds the `fnsql!` blocks under `src/`, prepares their queries
+//! against a database, and records the parameter and column types under `.fnsql/`:
+//!
+//! ```text
+//! cargo install fnsql-macro --features prepare-postgres --bin fnsql-prepare
+//! fnsql-prepare "host=localhost user=postgres" path/to/crate
+//! ```
+//!
+//! Each block is prepared in a transaction that is rolled back, after its schema
+//! queries (as in `check-rusqlite`) are executed. With the `check-postgres` manifest
+//! feature, the declared parameter and output types are then compared to the
+//! recorded ones during compilation, e.g. `i32` for a `count(*)` column is an
+//! error as it is an `int8`. A query without a recorded entry is an error too, so
+//! `fnsql-prepare` needs to run again whenever a query changes, unless the query is
+//! marked with `unchecked`. Like `check-rusqlite`, the feature is not part of
+//! `all`.
+//!
 //! ## Limitations
 //!
 //!  * Though it <i>does</i> provide auto-generated tests for validating queries in `cargo test`,
-//!    it does not do any compile-time validation based on the SQL query string.
+//!    compile-time validation of the SQL query string is limited to checking that its
+//!    placeholders match the declared parameters, unless `check-rusqlite` is enabled,
+//!    or `check-postgres` is enabled with an up-to-date `.fnsql/` cache.
+//!  * A `:name` right after a digit or the `[` of a subscript, e.g. in `arr[1:n]` or
+//!    `arr[:n]`, is part of an array slice rather than a placeholder.
 //!  * It only supports `rusqlite` and `postgres` for now.
 
-extern crate proc_macro;
+extern crate proc_macro;
+
+use std::ops::Range;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use quote::{quote, ToTokens};
+use syn::{parse_macro_input, Ident, LitStr};
+
+#[cfg(feature = "check-postgres")]
+mod cache;
+mod from_row;
+#[cfg(feature = "check-postgres")]
+mod postgres_check;
+mod query;
+mod sql;
+mod sql_file;
+#[cfg(feature = "check-rusqlite")]
+mod sqlite_check;
+
+use query::{
+    Bind, CopyDirection, Instance, Kind, Module, Output, Param, PayloadFormat, Queries, Query, Shape,
+};
+
+impl Kind {
+    fn row_getter(&self) -> Tokens {
+        match self {
+            Kind::Rusqlite => quote! {get},
+            Kind::PostgreSQL => quote! {try_get},
+        }
+    }
+
+    /// Declares an extension trait with the given methods, and implements it for the
+    /// connection types of the backend.
+    fn extension_trait(&self, name: &Ident, attrs: Tokens, impl_attrs: Tokens,
+        decls: Tokens, impls: Tokens) -> Tokens
+    {
+        let targets = match self {
+            Kind::Rusqlite => vec![quote! { impl #name for rusqlite::Connection }],
+            Kind::PostgreSQL => vec![
+                quote! { impl #name for postgres::Client },
+                quote! { impl<'a> #name for postgres::Transaction<'a> },
+            ],
+        };
+
+        quote! {
+            #attrs
+            #[allow(non_camel_case_types, deprecated, clippy::ptr_arg)]
+            pub trait #name {
+                #decls
+            }
+
+            #(
+                #impl_attrs
+                #[allow(deprecated, clippy::ptr_arg)]
+                #targets {
+                    #impls
+                }
+            )*
+        }
+    }
+}
+
+/// The code generated for a query: the methods of its extension trait, and the items
+/// that they use.
+struct Expansion {
+    decls: Tokens,
+    impls: Tokens,
+    items: Tokens,
+}
+
+impl Query {
+    fn prepend_name(&self, prefix: &'static str) -> Ident {
+        Ident::new(&format!("{}{}", prefix, &self.name), self.name.span())
+    }
+
+    /// Like `prepend_name`, but for items that the instances of a query with several
+    /// backends cannot share, which are then named after the backend as well, e.g.
+    /// `Statement_postgres_<name>`.
+    fn instance_name(&self, prefix: &'static str) -> Ident {
+        match self.instance {
+            Instance::Single => self.prepend_name(prefix),
+            Instance::First | Instance::Other => Ident::new(
+                &format!("{}{}_{}", prefix, self.kind.name(), &self.name), self.name.span()),
+        }
+    }
+
+    /// Test setup functions are named after the backend, so that the dependencies of
+    /// a query can be found regardless of the other backends that they are given.
+    fn testsetup_name(&self, name: &Ident) -> Ident {
+        Ident::new(&format!("testsetup_{}_{}", self.kind.name(), name), name.span())
+    }
+
+    fn params_declr(&self) -> Tokens {
+        let list: Vec<_> = self.params.iter().map(|x| x.expand_declr()).collect();
+        quote! { #(, #list)* }
+    }
+
+    fn outputs_declr(&self) -> Tokens {
+        let list: Vec<_> = self.outputs.iter().map(|x| x.expand_declr()).collect();
+        quote! { #(#list),* }
+    }
+
+    fn named_outputs(&self) -> bool {
+        self.outputs.iter().any(|x| x.name.is_some())
+    }
+
+    /// The type of a single returned row: either a user type implementing
+    /// `fnsql::FromRow`, the generated `Row_<name>` struct for named output
+    /// columns, or a tuple.
+    fn row_type(&self) -> Tokens {
+        if let Some(from_row) = &self.from_row {
+            quote! { #from_row }
+        } else if self.named_outputs() {
+            let row_struct = self.prepend_name("Row_");
+            quote! { #row_struct }
+        } else {
+            let outputs_declr = self.outputs_declr();
+            quote! { (#outputs_declr) }
+        }
+    }
+
+    /// Arguments of the closures passed to `query_map` and `query_row`.
+    fn row_args_declr(&self) -> Tokens {
+        if self.from_row.is_some() || self.named_outputs() {
+            self.row_type()
+        } else {
+            self.outputs_declr()
+        }
+    }
+
+    /// The forwarded `#[cfg(...)]` attributes, which go on every generated item.
+    fn cfg_attrs(&self) -> Tokens {
+        let attrs = self.attrs.iter().filter(|attr| attr.path.is_ident("cfg"));
+        quote! { #(#attrs)* }
+    }
+
+    /// The rest of the forwarded attributes, e.g. doc comments, which go on the
+    /// generated trait, its methods and the public types.
+    fn doc_attrs(&self) -> Tokens {
+        let attrs = self.attrs.iter().filter(|attr| !attr.path.is_ident("cfg"));
+        quote! { #(#attrs)* }
+    }
+
+    fn row_struct_def(&self) -> Tokens {
+        if !self.named_outputs() || self.instance == Instance::Other {
+            return quote! {};
+        }
+
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+
+        let row_struct = self.prepend_name("Row_");
+        let fields: Vec<_> = self.outputs.iter().map(|x| {
+            let name = &x.name;
+            let ttype = &x.ttype;
+            quote! { pub #name: #ttype }
+        }).collect();
+        let serde_derive = if self.serde {
+            quote! { #[derive(serde::Serialize, serde::Deserialize)] }
+        } else {
+            quote! {}
+        };
+
+        quote! {
+            #cfg_attrs
+            #doc_attrs
+            #[allow(non_camel_case_types)]
+            #[derive(Debug, Clone)]
+            #serde_derive
+            pub struct #row_struct {
+                #(#fields),*
+            }
+        }
+    }
+
+    fn outputs_row_getter(&self, getter: Tokens) -> Vec<Tokens> {
+        self.outputs
+            .iter()
+            .enumerate()
+            .map(|(i, _)| {
+                let i = syn::LitInt::new(&format!("{}", i), self.name.span());
+                quote! {row.#getter(#i)?}
+            })
+            .collect()
+    }
+
+    /// Positional row getters, to be relayed as closure arguments.
+    fn outputs_row_get_numbered(&self, kind: &Kind) -> Tokens {
+        if self.from_row.is_some() || self.named_outputs() {
+            return self.outputs_row_value(kind);
+        }
+
+        let list = self.outputs_row_getter(kind.row_getter());
+        quote! { #(#list),* }
+    }
+
+    /// An expression of `row_type()` built from `row`.
+    fn outputs_row_value(&self, kind: &Kind) -> Tokens {
+        if let Some(from_row) = &self.from_row {
+            return match kind {
+                Kind::Rusqlite => quote! {
+                    <#from_row as fnsql::FromRow<rusqlite::Row<'_>>>::from_row(row)
+                        .map_err(|err| fnsql::rows::driver_error(err)
+                            .unwrap_or_else(rusqlite::Error::UserFunctionError))?
+                },
+                Kind::PostgreSQL => quote! {
+                    <#from_row as fnsql::FromRow<postgres::Row>>::from_row(&row)
+                        .map_err(fnsql::rows::row_error)?
+                },
+            };
+        }
+
+        if self.shape == Shape::Scalar {
+            // The type is given explicitly, so that a type that is not a column type,
+            // e.g. a `FromRow` struct, is reported at the type.
+            let ttype = &self.outputs[0].ttype;
+            let getter = kind.row_getter();
+            return quote! { row.#getter::<_, #ttype>(0)? };
+        }
+
+        let list = self.outputs_row_getter(kind.row_getter());
+        if self.named_outputs() {
+            let row_struct = self.prepend_name("Row_");
+            let names = self.outputs.iter().map(|x| &x.name);
+            quote! { #row_struct { #(#names: #list),* } }
+        } else {
+            quote! { (#(#list),*) }
+        }
+    }
+
+    fn outputs_mapped_row_closure(&self) -> Tokens {
+        let list = self.outputs_row_get_numbered(&self.kind);
+        quote! { Ok(map(#list)) }
+    }
+
+    fn params_arbitrary(&self) -> (Tokens, Tokens) {
+        let mut gen_lets = vec![];
+        let mut params = vec![];
+
+        let _ = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(idx, param)| {
+                let owned_ttype = param.owned_type();
+                let ident = Ident::new(&format!("i_{}", idx), self.name.span());
+
+                gen_lets.push(quote! {
+                    let #ident: #owned_ttype = arbitrary::Arbitrary::arbitrary(uns).unwrap();
+                });
+                params.push(quote! {&#ident});
+            })
+            .collect::<Vec<()>>();
+
+        (quote! { #(#gen_lets);* }, quote! { #(#params),* })
+    }
+
+    fn params_query(&self) -> Tokens {
+        let list: Vec<_> = self.binds().iter().map(|x| x.expand_query(self)).collect();
+        if list.is_empty() {
+            quote! { [] }
+        } else {
+            quote! { &[#(#list),*] }
+        }
+    }
+
+    fn params_query_ref(&self) -> Tokens {
+        let list: Vec<_> = self.binds().iter().map(|x| x.expand_query(self)).collect();
+        if list.is_empty() {
+            quote! { &[] }
+        } else {
+            quote! { &[#(#list),*] }
+        }
+    }
+
+    fn params_relay(&self) -> Tokens {
+        self.params_relay_with_list(None)
+    }
+
+    /// Like `params_relay`, with the list parameter replaced by `list`, if given.
+    fn params_relay_with_list(&self, list: Option<&Ident>) -> Tokens {
+        let list: Vec<_> = self
+            .params
+            .iter()
+            .map(|x| {
+                let name = match list {
+                    Some(list) if x.list => list,
+                    _ => &x.name,
+                };
+                quote! { #name }
+            })
+            .collect();
+        if list.is_empty() {
+            quote! {}
+        } else {
+            quote! { #(#list),*, }
+        }
+    }
+
+    fn list_param(&self) -> Option<&Param> {
+        self.params.iter().find(|param| param.list)
+    }
+
+    /// Whether the SQL of the query is built at runtime, as it depends on the
+    /// arguments: on the optional fragments and assignments that are included, and for
+    /// `rusqlite`, on the length of the list.
+    fn is_dynamic(&self) -> bool {
+        !self.fragments().is_empty()
+            || self.update
+            || (self.kind == Kind::Rusqlite && self.list_param().is_some())
+    }
+
+    /// Whether the generated methods check more than the database driver does, i.e.
+    /// the assignments of an `update` query, the number of rows returned or affected,
+    /// or the payload of a `channel`.
+    fn wraps_errors(&self) -> bool {
+        self.update || self.shape != Shape::Rows || self.expect_rows.is_some()
+            || self.channel.is_some()
+    }
+
+    /// Whether converting a row may fail with an error other than the one of the
+    /// driver, which is the case for `FromRow` outputs of `postgres` queries, as
+    /// unlike `rusqlite::Error`, a `postgres::Error` cannot wrap it. Only the methods
+    /// that convert rows are affected.
+    fn wraps_row_errors(&self) -> bool {
+        self.kind == Kind::PostgreSQL && self.from_row.is_some()
+    }
+
+    /// A statement checking the number of rows affected by executing the query,
+    /// for `expect_rows`.
+    fn check_affected(&self, count: Tokens) -> Tokens {
+        let expected = match &self.expect_rows {
+            Some(expected) => expected,
+            None => return quote! {},
+        };
+        let (start, end) = (expected.start(), expected.end());
+        let count = match self.kind {
+            Kind::Rusqlite => quote! { #count as u64 },
+            Kind::PostgreSQL => count,
+        };
+        quote! {
+            fnsql::rows::expect_affected(#count, #start..=#end)?;
+        }
+    }
+
+    /// The error type of the generated methods, which is `fnsql::Error` for queries
+    /// that `wraps_errors()`.
+    fn error_type(&self) -> Tokens {
+        let driver = self.driver_error_type();
+        if self.wraps_errors() {
+            quote! { fnsql::Error<#driver> }
+        } else {
+            driver
+        }
+    }
+
+    /// The error type of converting a row, and of the generated methods that return
+    /// rows without checking their number.
+    fn row_error_type(&self) -> Tokens {
+        let driver = self.driver_error_type();
+        if self.wraps_row_errors() {
+            quote! { fnsql::Error<#driver> }
+        } else {
+            driver
+        }
+    }
+
+    fn driver_error_type(&self) -> Tokens {
+        match self.kind {
+            Kind::Rusqlite => quote! { rusqlite::Error },
+            Kind::PostgreSQL => quote! { postgres::Error },
+        }
+    }
+
+    /// An expression of `error_type()` from a driver error.
+    fn driver_error(&self, err: Tokens) -> Tokens {
+        if self.wraps_errors() {
+            quote! { fnsql::Error::Driver(#err) }
+        } else {
+            err
+        }
+    }
+
+    /// Statements that build the SQL of a dynamic query into `sql`, with the list
+    /// taken from `list`. Without `bind`, `list` is the length of the list, and only
+    /// the placeholders are added, for preparing a statement.
+    fn build_sql(&self, list: Tokens, bind: bool) -> Tokens {
+        let sql = self.query.value();
+        let mut steps = self.build_steps(&sql, 0..sql.len(), &self.fragments(), &[], &list, bind);
+        let assignments = self.assignments();
+        if let (Some((first, _)), Some((last, _))) = (assignments.first(), assignments.last()) {
+            // The `SET` clause is built separately, as its assignments are joined
+            // with commas.
+            let set = self.build_assignments(&sql, &assignments, &list, bind);
+            let before = self.build_steps(&sql, 0..first.start, &self.fragments(), &[], &list, bind);
+            let after = self.build_steps(&sql, last.end..sql.len(), &self.fragments(), &[], &list, bind);
+            steps = before.into_iter().chain(set).chain(after).collect();
+        }
+        let (param_type, prefix) = match self.kind {
+            Kind::Rusqlite => (quote! { dyn rusqlite::ToSql + '_ }, '?'),
+            Kind::PostgreSQL => (quote! { dyn postgres::types::ToSql + Sync + '_ }, '$'),
+        };
+
+        quote! {
+            let mut sql = fnsql::builder::SqlBuilder::<#param_type>::new(#prefix);
+            #(#steps)*
+        }
+    }
+
+    /// The steps of `build_sql` for the assignments of an `update` query, which are
+    /// included when the `Option` parameters that they depend on are `Some`.
+    fn build_assignments(&self, sql: &str, assignments: &[(Range<usize>, Vec<&Ident>)],
+        list: &Tokens, bind: bool) -> Vec<Tokens>
+    {
+        let mut steps = vec![quote! { let mut assigned = 0; }];
+
+        for (range, depends) in assignments {
+            let inner = self.build_steps(sql, range.clone(), &[], depends, list, bind);
+            let body = quote! {
+                if assigned > 0 {
+                    sql.push(", ");
+                }
+                assigned += 1;
+                #(#inner)*
+            };
+            steps.push(if depends.is_empty() {
+                quote! { { #body } }
+            } else {
+                quote! {
+                    if let (#(Some(#depends),)*) = (#(#depends,)*) {
+                        #body
+                    }
+                }
+            });
+        }
+
+        steps.push(quote! {
+            if assigned == 0 {
+                return Err(fnsql::Error::NoAssignments);
+            }
+        });
+        steps
+    }
+
+    /// The steps of `build_sql` for a range of the SQL. Inside an optional fragment,
+    /// the `Option` parameters that it depends on are `unwrapped`.
+    fn build_steps(&self, sql: &str, range: Range<usize>, fragments: &[Range<usize>],
+        unwrapped: &[&Ident], list: &Tokens, bind: bool) -> Vec<Tokens>
+    {
+        enum Item<'a> {
+            Fragment(Range<usize>),
+            Bind(Range<usize>, Bind<'a>),
+        }
+
+        let mut items: Vec<_> = fragments
+            .iter()
+            .filter(|fragment| range.contains(&fragment.start))
+            .cloned()
+            .map(Item::Fragment)
+            .collect();
+        for (placeholder_range, placeholder) in sql::placeholders(sql) {
+            let inside_fragment = fragments.iter().any(|x| x.contains(&placeholder_range.start));
+            if !range.contains(&placeholder_range.start) || inside_fragment {
+                continue;
+            }
+            if let Some(bind) = self.bind_of(&placeholder) {
+                items.push(Item::Bind(placeholder_range, bind));
+            }
+        }
+        items.sort_by_key(|item| match item {
+            Item::Fragment(range) | Item::Bind(range, _) => range.start,
+        });
+
+        let cast = match self.kind {
+            Kind::Rusqlite => quote! { &dyn rusqlite::ToSql },
+            Kind::PostgreSQL => quote! { &(dyn postgres::types::ToSql + Sync) },
+        };
+        let mut steps = vec![];
+        let mut last = range.start;
+        let push_text = |steps: &mut Vec<Tokens>, text: &str| {
+            if !text.is_empty() {
+                steps.push(quote! { sql.push(#text); });
+            }
+        };
+
+        for item in items {
+            match item {
+                Item::Fragment(fragment) => {
+                    push_text(&mut steps, &sql[last..fragment.start]);
+                    let inner = fragment.start + 1..fragment.end - 1;
+                    let mut conditions = vec![];
+                    for (_, placeholder) in sql::placeholders(&sql[inner.clone()]) {
+                        match self.option_param_of(&placeholder) {
+                            Some(param) if !conditions.contains(&&param.name) => {
+                                conditions.push(&param.name);
+                            }
+                            _ => {}
+                        }
+                    }
+                    let inner_steps = self.build_steps(sql, inner, &[], &conditions, list, bind);
+                    steps.push(quote! {
+                        if let (#(Some(#conditions),)*) = (#(#conditions,)*) {
+                            #(#inner_steps)*
+                        }
+                    });
+                    last = fragment.end;
+                }
+                Item::Bind(placeholder_range, item) => {
+                    let param = item.param;
+                    let name = &param.name;
+                    let value = item.value();
+                    let in_list = match self.kind {
+                        Kind::PostgreSQL if param.list => sql::in_list(sql, placeholder_range.clone()),
+                        _ => None,
+                    };
+
+                    if let Some((list_range, negated)) = in_list {
+                        push_text(&mut steps, &sql[last..list_range.start]);
+                        push_text(&mut steps, if negated { "<> ALL(" } else { "= ANY(" });
+                        steps.push(quote! { sql.bind(&#name as #cast); });
+                        push_text(&mut steps, ")");
+                        last = list_range.end;
+                        continue;
+                    }
+
+                    push_text(&mut steps, &sql[last..placeholder_range.start]);
+                    steps.push(match (param.list, bind) {
+                        (true, true) => quote! {
+                            sql.bind_list(#list.iter().map(|x| x as #cast));
+                        },
+                        (true, false) => quote! { sql.placeholders(#list); },
+                        (false, true) if unwrapped.contains(&name) => quote! {
+                            sql.bind(#name as #cast);
+                        },
+                        (false, true) => quote! { sql.bind(&#value as #cast); },
+                        (false, false) => quote! { sql.placeholders(1); },
+                    });
+                    last = placeholder_range.end;
+                }
+            }
+        }
+
+        push_text(&mut steps, &sql[last..range.end]);
+        steps
+    }
 
-use std::collections::HashMap;
+    /// The number of placeholders of a `rusqlite` query other than those of its list,
+    /// which limits the length of the list chunks.
+    fn sqlite_fixed_placeholders(&self) -> usize {
+        sql::named_placeholders(&self.query.value())
+            .into_iter()
+            .filter(|name| !self.params.iter().any(|param| param.list && param.name == name))
+            .count()
+    }
 
-use proc_macro::TokenStream;
-use proc_macro2::TokenStream as Tokens;
-use quote::{quote, ToTokens};
-use regex::{Regex, Captures};
-use syn::{
-    braced, bracketed, parenthesized,
-    parse::{Parse, ParseStream},
-    parse_macro_input,
-    punctuated::Punctuated,
-    token, Ident, Token, LitStr,
-};
+    fn expand_parts(&self) -> Expansion {
+        // Errors are emitted along with the expansion, so that they are not
+        // buried under errors about missing methods.
+        let errors = match self.check_placeholders() {
+            Ok(()) => quote! {},
+            Err(_) if self.instance == Instance::Other && !self.variant => quote! {},
+            Err(err) => err.to_compile_error(),
+        };
 
-struct Queries {
-    list: Vec<Query>,
-}
+        let mut expansion = match self.kind {
+            Kind::Rusqlite => self.sqlite_expand(),
+            Kind::PostgreSQL => self.postgres_expand(),
+        };
 
-impl Parse for Queries {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let mut list = vec![];
-        while !input.is_empty() {
-            list.push(input.parse()?)
+        let items = expansion.items;
+        expansion.items = quote! {
+            #errors
+            #items
+        };
+        expansion
+    }
+
+    /// Expands the query on its own, with an extension trait named after it.
+    fn expand(&self) -> Tokens {
+        let trait_name = match self.kind {
+            Kind::Rusqlite => self.prepend_name("Connection_"),
+            Kind::PostgreSQL => self.prepend_name("Client_"),
+        };
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+        let Expansion { decls, impls, items } = self.expand_parts();
+        let extension_trait = self.kind.extension_trait(&trait_name,
+            quote! { #cfg_attrs #doc_attrs }, cfg_attrs, decls, impls);
+
+        quote! {
+            #extension_trait
+            #items
         }
+    }
 
-        Ok(Queries { list })
+    /// The name of the only method of a query that returns a single row.
+    fn single_row_name(&self) -> Option<Ident> {
+        match self.shape {
+            Shape::Rows => None,
+            Shape::One => Some(self.prepend_name("query_one_")),
+            Shape::Opt => Some(self.prepend_name("query_opt_")),
+            Shape::Scalar => Some(self.prepend_name("query_scalar_")),
+        }
     }
-}
 
-enum Kind {
-    Rusqlite,
-    PostgreSQL,
-}
+    /// The expansion of a query that returns a single row, e.g. `-> (i32, String)`,
+    /// which only has a method fetching it, failing when the number of rows does
+    /// not match.
+    fn single_row_expand(&self, method_name: Ident) -> Expansion {
+        let params_declr = self.params_declr();
+        let row_type = self.row_type();
+        let row_struct_def = self.row_struct_def();
+        let row_value = self.outputs_row_value(&self.kind);
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+        let error_type = self.error_type();
+        let row_error_type = self.row_error_type();
+        let convert_row = self.prepend_name("convert_row_");
 
-struct Query {
-    name: Ident,
-    params: Vec<Param>,
-    outputs: Vec<Output>,
-    query: syn::LitStr,
-    kind: Kind,
-    test: Option<Vec<String>>,
-    named: bool,
-}
+        let (return_type, check) = match self.shape {
+            Shape::Opt => (quote! { Option<#row_type> }, quote! { fnsql::rows::opt }),
+            _ => (quote! { #row_type }, quote! { fnsql::rows::one }),
+        };
 
-impl Parse for Query {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let mut kind = None;
-        let mut test = None;
-        let mut named = false;
-
-        if input.peek(Token![#]) {
-            let _: Token![#] = input.parse()?;
-            let content;
-            let _ = bracketed!(content in input);
-            let list: Punctuated<Attr, Token![,]> = content.parse_terminated(Parse::parse)?;
-
-            for attr in list {
-                match attr {
-                    Attr::Kind(attr_kind) => {
-                        kind = Some(attr_kind);
+        let (receiver, body, convert) = match self.kind {
+            Kind::Rusqlite if self.is_dynamic() => {
+                let for_each_chunk = self.sqlite_for_each_chunk(quote! {
+                    let mut stmt = self.prepare_cached(sql.text())?;
+                    rows.extend(stmt.query(sql.params())?.mapped(|row| Ok(#row_value)));
+                });
+                (quote! { &self }, quote! {
+                    let mut rows = vec![];
+                    #for_each_chunk
+                    #check(rows)
+                }, quote! {})
+            }
+            Kind::Rusqlite => {
+                let query = &self.query;
+                let params_query = self.params_query();
+                (quote! { &self }, quote! {
+                    let mut stmt = self.prepare_cached(#query)?;
+                    let rows = stmt.query(#params_query)?.mapped(|row| Ok(#row_value));
+                    #check(rows)
+                }, quote! {})
+            }
+            Kind::PostgreSQL => {
+                let (build, query, params) = if self.is_dynamic() {
+                    (self.build_sql(quote! {}, true), quote! { sql.text() }, quote! { sql.params() })
+                } else {
+                    let query = LitStr::new(&self.postgres_sql(), self.query.span());
+                    (quote! {}, quote! { #query }, self.params_query_ref())
+                };
+                (quote! { &mut self }, quote! {
+                    #build
+                    let rows = self.query(#query, #params)?;
+                    #check(rows.into_iter().map(#convert_row))
+                }, quote! {
+                    #cfg_attrs
+                    #[allow(deprecated, clippy::needless_question_mark)]
+                    pub fn #convert_row(row: postgres::Row) -> Result<#row_type, #row_error_type> {
+                        Ok(#row_value)
                     }
-                    Attr::Test(test_attrs) => {
-                        if test.is_none() {
-                            test = Some(vec![]);
+                })
+            }
+        };
+
+        let decls = quote! {
+            #cfg_attrs
+            #doc_attrs
+            fn #method_name(#receiver #params_declr) -> Result<#return_type, #error_type>;
+        };
+
+        let impls = quote! {
+            #cfg_attrs
+            #[allow(clippy::needless_question_mark)]
+            fn #method_name(#receiver #params_declr) -> Result<#return_type, #error_type> {
+                #body
+            }
+        };
+
+        let test_code = self.test_code();
+        let items = quote! {
+            #row_struct_def
+            #convert
+            #test_code
+        };
+
+        Expansion { decls, impls, items }
+    }
+
+    /// The declaration and the implementation of `insert_many_<name>`, which executes
+    /// a statement prepared once for each item, within a savepoint. Items are tuples
+    /// of the parameters, or the parameter itself if there is only one, and can be
+    /// either owned or borrowed.
+    fn insert_many(&self) -> (Tokens, Tokens) {
+        if !self.many || self.check_placeholders().is_err() {
+            return (quote! {}, quote! {});
+        }
+
+        let insert_many_name = self.prepend_name("insert_many_");
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+        let error_type = self.error_type();
+        let check_affected = self.check_affected(quote! { affected });
+
+        let names: Vec<_> = self.params.iter().map(|param| &param.name).collect();
+        let generics: Vec<_> = (0..names.len())
+            .map(|idx| Ident::new(&format!("P{}", idx), self.name.span()))
+            .collect();
+        let bounds = self.params.iter().zip(&generics).map(|(param, generic)| {
+            let ttype = &param.ttype;
+            quote! { #generic: std::borrow::Borrow<#ttype> }
+        });
+        let (item, pattern) = if names.len() == 1 {
+            (quote! { #(#generics)* }, quote! { #(#names)* })
+        } else {
+            (quote! { (#(#generics),*) }, quote! { (#(#names),*) })
+        };
+        let borrows = self.params.iter().map(|param| {
+            let name = &param.name;
+            let ttype = &param.ttype;
+            quote! { let #name: &#ttype = #name.borrow(); }
+        });
+        let borrows = quote! { #(#borrows)* };
+        let receiver = match self.kind {
+            Kind::Rusqlite => quote! { &self },
+            Kind::PostgreSQL => quote! { &mut self },
+        };
+        let signature = quote! {
+            fn #insert_many_name<I, #(#generics),*>(#receiver, rows: I) -> Result<u64, #error_type>
+            where
+                I: IntoIterator<Item = #item>,
+                #(#bounds),*
+        };
+
+        let body = match self.kind {
+            Kind::Rusqlite => {
+                // The savepoint is handled by statements, as `rusqlite` only takes a
+                // savepoint of a mutable connection.
+                let query = &self.query;
+                let params_query = self.params_query();
+                quote! {
+                    self.execute_batch("SAVEPOINT fnsql_insert_many")?;
+                    #[allow(clippy::redundant_closure_call)]
+                    let result = (|| -> Result<u64, #error_type> {
+                        let mut stmt = self.prepare_cached(#query)?;
+                        let mut count = 0;
+                        for #pattern in rows {
+                            #borrows
+                            let affected = stmt.execute(#params_query)?;
+                            #check_affected
+                            count += affected as u64;
                         }
-                        for test_attr in test_attrs {
-                            match test_attr {
-                                TestAttr::With(v) => {
-                                    test.as_mut().unwrap().extend(v);
-                                }
-                            }
+                        Ok(count)
+                    })();
+                    match result {
+                        Ok(_) => self.execute_batch("RELEASE fnsql_insert_many")?,
+                        Err(_) => {
+                            let _ = self.execute_batch(
+                                "ROLLBACK TO fnsql_insert_many; RELEASE fnsql_insert_many");
                         }
                     }
-                    Attr::Named => {
-                        named = true;
-                    },
+                    result
+                }
+            }
+            Kind::PostgreSQL => {
+                let query = LitStr::new(&self.postgres_sql(), self.query.span());
+                let params_query_ref = self.params_query_ref();
+                quote! {
+                    let mut tx = self.transaction()?;
+                    let stmt = tx.prepare(#query)?;
+                    let mut count = 0;
+                    for #pattern in rows {
+                        #borrows
+                        let affected = tx.execute(&stmt, #params_query_ref)?;
+                        #check_affected
+                        count += affected;
+                    }
+                    tx.commit()?;
+                    Ok(count)
                 }
             }
         };
 
-        let name = input.parse()?;
-        let kind = match kind {
-            None => panic!("unknown SQL type. Supported: rusqlite"),
-            Some(kind) => kind,
+        let decl = quote! {
+            #cfg_attrs
+            #doc_attrs
+            #signature;
         };
-        let content;
-        let _ = parenthesized!(content in input);
-        let list: Punctuated<_, Token![,]> = content.parse_terminated(Parse::parse)?;
-        let params = list.into_iter().collect();
-
-        let outputs = if input.peek(Token![->]) {
-            let _: Token![->] = input.parse()?;
-
-            let content;
-            let _ = bracketed!(content in input);
+        let imp = quote! {
+            #cfg_attrs
+            #signature
             {
-                let sub_content;
-                let _ = parenthesized!(sub_content in content);
-                let list: Punctuated<_, Token![,]> = sub_content.parse_terminated(Parse::parse)?;
-                list.into_iter().collect()
+                use std::borrow::Borrow;
+                #body
             }
-        } else {
-            vec![]
         };
 
-        let content;
-        let _ = braced!(content in input);
-        let query = content.parse::<syn::LitStr>()?;
-
-        Ok(Query {
-            name,
-            params,
-            outputs,
-            query,
-            kind,
-            test,
-            named,
-        })
-    }
-}
-
-impl Query {
-    fn prepend_name(&self, prefix: &'static str) -> Ident {
-        Ident::new(&format!("{}{}", prefix, &self.name), self.name.span())
+        (decl, imp)
     }
 
-    fn params_declr(&self) -> Tokens {
-        let list: Vec<_> = self.params.iter().map(|x| x.expand_declr()).collect();
-        quote! { #(, #list)* }
-    }
+    /// The expansion of a `script` query, which only has a method executing all of
+    /// its statements.
+    fn script_expand(&self) -> Expansion {
+        let execute_batch_name = self.prepend_name("execute_batch_");
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+
+        let (receiver, error_type, body) = match self.kind {
+            Kind::Rusqlite => {
+                let query = &self.query;
+                (quote! { &self }, quote! { rusqlite::Error }, quote! {
+                    self.execute_batch(#query)
+                })
+            }
+            Kind::PostgreSQL => {
+                let query = LitStr::new(&self.postgres_sql(), self.query.span());
+                (quote! { &mut self }, quote! { postgres::Error }, quote! {
+                    self.batch_execute(#query)
+                })
+            }
+        };
 
-    fn outputs_declr(&self) -> Tokens {
-        let list: Vec<_> = self.outputs.iter().map(|x| x.expand_declr()).collect();
-        quote! { #(#list),* }
-    }
+        let decls = quote! {
+            #cfg_attrs
+            #doc_attrs
+            fn #execute_batch_name(#receiver) -> Result<(), #error_type>;
+        };
 
-    fn outputs_row_get_numbered(&self) -> Tokens {
-        let list: Vec<_> = self
-            .outputs
-            .iter()
-            .enumerate()
-            .map(|(i, _)| {
-                let i = syn::LitInt::new(&format!("{}", i), self.name.span());
-                quote! {row.get(#i)?}
-            })
-            .collect();
+        let impls = quote! {
+            #cfg_attrs
+            fn #execute_batch_name(#receiver) -> Result<(), #error_type> {
+                #body
+            }
+        };
 
-        quote! { #(#list),* }
+        Expansion { decls, impls, items: self.test_code() }
     }
 
-    fn outputs_row_try_get_numbered(&self) -> Tokens {
-        let list: Vec<_> = self
-            .outputs
-            .iter()
-            .enumerate()
-            .map(|(i, _)| {
-                let i = syn::LitInt::new(&format!("{}", i), self.name.span());
-                quote! {row.try_get(#i)?}
-            })
-            .collect();
+    /// The expansion of a `copy_in` or `copy_out` query, which has a method starting the
+    /// binary `COPY`, and a type writing or reading its rows. The types of the columns are
+    /// found by preparing a `SELECT` of them.
+    fn copy_expand(&self, copy: CopyDirection) -> Expansion {
+        let statement = match self.copy_statement() {
+            Some(statement) => statement,
+            // The query is reported by `check_placeholders`.
+            None => return Expansion { decls: quote! {}, impls: quote! {}, items: quote! {} },
+        };
+        let select = LitStr::new(&statement.select, self.query.span());
+        let query = &self.query;
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+        let test_code = self.test_code();
+        let column_types = quote! {
+            let types: Vec<_> = self.prepare(#select)?
+                .columns()
+                .iter()
+                .map(|column| column.type_().clone())
+                .collect();
+        };
 
-        quote! { #(#list),* }
-    }
+        let (method_name, type_name, type_def, body) = match copy {
+            CopyDirection::In => {
+                #[allow(non_snake_case)]
+                let CopyIn = self.prepend_name("CopyIn_");
+                let params_declr = self.params_declr();
+                let params_query_ref = self.params_query_ref();
+                let type_def = quote! {
+                    #cfg_attrs
+                    #doc_attrs
+                    #[allow(non_camel_case_types)]
+                    pub struct #CopyIn<'a>(pub postgres::binary_copy::BinaryCopyInWriter<'a>);
+
+                    #cfg_attrs
+                    #[allow(deprecated)]
+                    impl<'a> #CopyIn<'a> {
+                        /// Writes a row.
+                        pub fn write(&mut self #params_declr) -> Result<(), postgres::Error> {
+                            self.0.write(#params_query_ref)
+                        }
 
-    fn outputs_mapped_row_closure(&self) -> Tokens {
-        let list = self.outputs_row_get_numbered();
-        quote! { Ok(map(#list)) }
-    }
+                        /// Completes the copy, returning the number of rows written. The copy
+                        /// is aborted if the writer is dropped without it.
+                        pub fn finish(self) -> Result<u64, postgres::Error> {
+                            self.0.finish()
+                        }
+                    }
+                };
+                let body = quote! {
+                    #column_types
+                    let writer = self.copy_in(#query)?;
+                    Ok(#CopyIn(postgres::binary_copy::BinaryCopyInWriter::new(writer, &types)))
+                };
+                (self.prepend_name("copy_in_"), CopyIn, type_def, body)
+            }
+            CopyDirection::Out => {
+                #[allow(non_snake_case)]
+                let CopyOut = self.prepend_name("CopyOut_");
+                let row_type = self.row_type();
+                let row_struct_def = self.row_struct_def();
+                let row_value = self.outputs_row_value(&self.kind);
+                let type_def = quote! {
+                    #row_struct_def
+
+                    #cfg_attrs
+                    #doc_attrs
+                    #[allow(non_camel_case_types)]
+                    pub struct #CopyOut<'a>(pub postgres::binary_copy::BinaryCopyOutIter<'a>);
+
+                    #cfg_attrs
+                    #[allow(deprecated)]
+                    impl<'a> #CopyOut<'a> {
+                        #[allow(clippy::needless_question_mark)]
+                        fn convert_row(row: postgres::binary_copy::BinaryCopyOutRow)
+                            -> Result<#row_type, postgres::Error>
+                        {
+                            Ok(#row_value)
+                        }
+                    }
 
-    fn params_arbitrary(&self) -> (Tokens, Tokens) {
-        let mut gen_lets = vec![];
-        let mut params = vec![];
+                    #cfg_attrs
+                    #[allow(deprecated)]
+                    impl<'a> Iterator for #CopyOut<'a> {
+                        type Item = Result<#row_type, postgres::Error>;
 
-        let _ = self
-            .params
-            .iter()
-            .enumerate()
-            .map(|(idx, param)| {
-                let ttype = &param.ttype;
-                let owned_ttype = if ttype.to_token_stream().to_string() == "str" {
-                    quote! {String}
-                } else if ttype.to_token_stream().to_string() == "[u8]" {
-                    quote! {Vec<u8>}
-                } else {
-                    quote! {#ttype}
+                        fn next(&mut self) -> Option<Self::Item> {
+                            let row = postgres::fallible_iterator::FallibleIterator::next(&mut self.0);
+                            row.transpose().map(|row| row.and_then(Self::convert_row))
+                        }
+                    }
                 };
-                let ident = Ident::new(&format!("i_{}", idx), self.name.span());
+                let body = quote! {
+                    #column_types
+                    let reader = self.copy_out(#query)?;
+                    Ok(#CopyOut(postgres::binary_copy::BinaryCopyOutIter::new(reader, &types)))
+                };
+                (self.prepend_name("copy_out_"), CopyOut, type_def, body)
+            }
+        };
 
-                gen_lets.push(quote! {
-                    let #ident: #owned_ttype = arbitrary::Arbitrary::arbitrary(uns).unwrap();
-                });
-                params.push(quote! {&#ident});
-            })
-            .collect::<Vec<()>>();
+        let decls = quote! {
+            #cfg_attrs
+            #doc_attrs
+            fn #method_name(&mut self) -> Result<#type_name<'_>, postgres::Error>;
+        };
 
-        (quote! { #(#gen_lets);* }, quote! { #(#params),* })
-    }
+        let impls = quote! {
+            #cfg_attrs
+            fn #method_name(&mut self) -> Result<#type_name<'_>, postgres::Error> {
+                #body
+            }
+        };
 
-    fn params_query(&self) -> Tokens {
-        let list: Vec<_> = self.params.iter().map(|x| x.expand_query(self)).collect();
-        if list.len() == 0 {
-            quote! { [] }
-        } else {
-            quote! { &[#(#list),*] }
+        Expansion {
+            decls,
+            impls,
+            items: quote! {
+                #type_def
+                #test_code
+            },
         }
     }
 
-    fn params_query_ref(&self) -> Tokens {
-        let list: Vec<_> = self.params.iter().map(|x| x.expand_query(self)).collect();
-        if list.len() == 0 {
-            quote! { &[] }
-        } else {
-            quote! { &[#(#list),*] }
-        }
-    }
+    /// The expansion of a `channel`, which has methods listening to it and notifying it,
+    /// and an iterator type over its notifications that decodes their payloads.
+    fn channel_expand(&self, format: PayloadFormat) -> Expansion {
+        let listen_name = self.prepend_name("listen_");
+        let unlisten_name = self.prepend_name("unlisten_");
+        let notify_name = self.prepend_name("notify_");
+        #[allow(non_snake_case)]
+        let Notifications = self.prepend_name("Notifications_");
+        let params_declr = self.params_declr();
+        let payload = &self.params[0].name;
+        let payload_type = self.params[0].owned_type();
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+        let method_attrs = quote! { #cfg_attrs #doc_attrs };
+        let test_code = self.test_code();
 
-    fn params_relay(&self) -> Tokens {
-        let list: Vec<_> = self
-            .params
-            .iter()
-            .map(|x| {
-                let name = &x.name;
-                quote! { #name }
-            })
-            .collect();
-        if list.len() == 0 {
-            quote! {}
-        } else {
-            quote! { #(#list),*, }
-        }
+        let channel = &self.query;
+        let quoted = format!("\"{}\"", channel.value().replace('"', "\"\""));
+        let listen = LitStr::new(&format!("LISTEN {}", quoted), channel.span());
+        let unlisten = LitStr::new(&format!("UNLISTEN {}", quoted), channel.span());
+
+        let (encode, decode) = match format {
+            PayloadFormat::Text => (
+                quote! { #payload.to_string() },
+                quote! { payload.parse::<#payload_type>() },
+            ),
+            PayloadFormat::Json => (
+                quote! { serde_json::to_string(#payload).map_err(|err| fnsql::Error::Payload(err.into()))? },
+                quote! { serde_json::from_str::<#payload_type>(payload) },
+            ),
+        };
+
+        let decls = quote! {
+            #method_attrs
+            fn #listen_name(&mut self) -> Result<(), postgres::Error>;
+            #method_attrs
+            fn #unlisten_name(&mut self) -> Result<(), postgres::Error>;
+            #method_attrs
+            fn #notify_name(&mut self #params_declr) -> Result<(), fnsql::Error<postgres::Error>>;
+        };
+
+        let impls = quote! {
+            #cfg_attrs
+            fn #listen_name(&mut self) -> Result<(), postgres::Error> {
+                self.batch_execute(#listen)
+            }
+
+            #cfg_attrs
+            fn #unlisten_name(&mut self) -> Result<(), postgres::Error> {
+                self.batch_execute(#unlisten)
+            }
+
+            #cfg_attrs
+            fn #notify_name(&mut self #params_declr) -> Result<(), fnsql::Error<postgres::Error>> {
+                let payload = #encode;
+                self.execute("SELECT pg_notify($1, $2)", &[&#channel, &payload])?;
+                Ok(())
+            }
+        };
+
+        let items = quote! {
+            #cfg_attrs
+            #doc_attrs
+            #[allow(non_camel_case_types)]
+            pub struct #Notifications<I>(pub I);
+
+            #cfg_attrs
+            #[allow(deprecated)]
+            impl<I> Iterator for #Notifications<I>
+            where
+                I: postgres::fallible_iterator::FallibleIterator<
+                    Item = postgres::Notification, Error = postgres::Error>,
+            {
+                type Item = Result<#payload_type, fnsql::Error<postgres::Error>>;
+
+                fn next(&mut self) -> Option<Self::Item> {
+                    // Notifications of other channels are skipped.
+                    loop {
+                        match postgres::fallible_iterator::FallibleIterator::next(&mut self.0) {
+                            Ok(Some(notification)) if notification.channel() == #channel => {
+                                let payload = notification.payload();
+                                return Some(#decode.map_err(|err| fnsql::Error::Payload(err.into())));
+                            }
+                            Ok(Some(_)) => {}
+                            Ok(None) => return None,
+                            Err(err) => return Some(Err(err.into())),
+                        }
+                    }
+                }
+            }
+
+            #test_code
+        };
+
+        Expansion { decls, impls, items }
     }
 
-    fn expand(&self) -> Tokens {
-        match self.kind {
-            Kind::Rusqlite => self.sqlite_expand(),
-            Kind::PostgreSQL => self.postgres_expand(),
+    fn postgres_expand(&self) -> Expansion {
+        if self.script {
+            return self.script_expand();
+        }
+        if let Some(copy) = self.copy {
+            return self.copy_expand(copy);
+        }
+        if let Some(format) = self.channel {
+            return self.channel_expand(format);
+        }
+        if let Some(name) = self.single_row_name() {
+            return self.single_row_expand(name);
+        }
+        if self.is_dynamic() {
+            return self.postgres_dynamic_expand();
         }
-    }
 
-    fn postgres_expand(&self) -> Tokens {
-        #[allow(non_snake_case)]
-        let Client = self.prepend_name("Client_");
         #[allow(non_snake_case)]
-        let Statement = self.prepend_name("Statement_");
+        let Statement = self.instance_name("Statement_");
         let execute_name = self.prepend_name("execute_");
         let execute_prepared_name = self.prepend_name("execute_prepared_");
         let prepare_name = self.prepend_name("prepare_");
-        let prepare_cached_name = self.prepend_name("prepare_cached_");
         let convert_row = self.prepend_name("convert_row_");
         let query_name = self.prepend_name("query_");
         let query_prepared_name = self.prepend_name("query_prepared_");
@@ -372,43 +1580,29 @@ impl Query {
         let query_opt_prepared_name = self.prepend_name("query_opt_prepared_");
         let params_declr = self.params_declr();
         let params_query_ref = self.params_query_ref();
-        let outputs_declr = self.outputs_declr();
-        let row_try_get_numbered = self.outputs_row_try_get_numbered();
-
-        let query;
-        if self.named {
-            lazy_static::lazy_static! {
-                static ref RE: Regex = Regex::new(":([A-Za-z_][_A-Za-z0-9]*)($|[^_A-Za-z0-9])").unwrap();
-            }
-
-            let params: HashMap<_, _> = self
-                .params
-                .iter()
-                .enumerate()
-                .map(|(idx, param)| {
-                    (format!("{}", param.name), idx)
-                }).collect();
-
-            query = String::from(RE.replace_all(&self.query.value(), |captures: &Captures| {
-                let c1 = captures.get(1).unwrap().as_str();
-                let c2 = captures.get(2).unwrap().as_str();
-                match params.get(c1) {
-                    Some(idx) => format!("${}{}", idx + 1, c2),
-                    None => format!("{}{}", c1, c2),
-                }
-            }));
-        } else {
-            query = self.query.value();
-        };
+        let row_type = self.row_type();
+        let row_struct_def = self.row_struct_def();
+        let row_value = self.outputs_row_value(&self.kind);
+        let error_type = self.error_type();
+        let row_error_type = self.row_error_type();
+        let check_affected = self.check_affected(quote! { count });
+
+        let query = self.postgres_sql();
         let query = LitStr::new(query.as_str(), self.query.span());
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+        let method_attrs = quote! { #cfg_attrs #doc_attrs };
 
         #[cfg(feature = "prepare-cache")]
         let (prepare_cached_decl, prepare_cached_impl) = {
+            let prepare_cached_name = self.prepend_name("prepare_cached_");
             let prepare_cached_decl = quote! {
+                #method_attrs
                 fn #prepare_cached_name(&mut self, cache: &mut fnsql::postgres::Cache) -> Result<#Statement, postgres::Error>;
             };
 
             let prepare_cached_impl = quote! {
+                #cfg_attrs
                 fn #prepare_cached_name(&mut self, cache: &mut fnsql::postgres::Cache) -> Result<#Statement, postgres::Error> {
                     Ok(#Statement(cache.prepare(#query, self)?))
                 }
@@ -422,174 +1616,493 @@ impl Query {
             (quote!{}, quote!{})
         };
 
+        let decls = quote! {
+            #method_attrs
+            fn #prepare_name(&mut self) -> Result<#Statement, postgres::Error>;
+            #prepare_cached_decl
+            #method_attrs
+            fn #execute_name(&mut self #params_declr) -> Result<u64, #error_type>;
+            #method_attrs
+            fn #execute_prepared_name(&mut self, stmt: &#Statement #params_declr)
+                -> Result<u64, #error_type>;
+            #method_attrs
+            fn #query_name(&mut self #params_declr) -> Result<Vec<#row_type>, #row_error_type>;
+            #method_attrs
+            fn #query_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<Vec<#row_type>, #row_error_type>;
+            #method_attrs
+            fn #query_one_name(&mut self #params_declr) -> Result<#row_type, #row_error_type>;
+            #method_attrs
+            fn #query_one_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<#row_type, #row_error_type>;
+            #method_attrs
+            fn #query_opt_name(&mut self #params_declr) -> Result<Option<#row_type>, #row_error_type>;
+            #method_attrs
+            fn #query_opt_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<Option<#row_type>, #row_error_type>;
+        };
+
         let defs = quote! {
+            #cfg_attrs
+            #doc_attrs
             #[allow(non_camel_case_types)]
             pub struct #Statement(pub postgres::Statement);
 
-            #[allow(non_camel_case_types)]
-            pub trait #Client {
-                fn #prepare_name(&mut self) -> Result<#Statement, postgres::Error>;
-                #prepare_cached_decl
-                fn #execute_name(&mut self #params_declr) -> Result<u64, postgres::Error>;
-                fn #execute_prepared_name(&mut self, stmt: &#Statement #params_declr)
-                    -> Result<u64, postgres::Error>;
-                fn #query_name(&mut self #params_declr) -> Result<Vec<(#outputs_declr)>, postgres::Error>;
-                fn #query_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<Vec<(#outputs_declr)>, postgres::Error>;
-                fn #query_one_name(&mut self #params_declr) -> Result<(#outputs_declr), postgres::Error>;
-                fn #query_one_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<(#outputs_declr), postgres::Error>;
-                fn #query_opt_name(&mut self #params_declr) -> Result<Option<(#outputs_declr)>, postgres::Error>;
-                fn #query_opt_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<Option<(#outputs_declr)>, postgres::Error>;
-            }
+            #row_struct_def
 
-            pub fn #convert_row(row: postgres::Row) -> Result<(#outputs_declr), postgres::Error> {
-                Ok((#row_try_get_numbered))
+            #cfg_attrs
+            #[allow(deprecated, clippy::needless_question_mark)]
+            pub fn #convert_row(row: postgres::Row) -> Result<#row_type, #row_error_type> {
+                Ok(#row_value)
             }
         };
 
         let timpl = quote! {
+            #cfg_attrs
             fn #prepare_name(&mut self)  -> Result<#Statement, postgres::Error> {
                 self.prepare(#query).map(#Statement)
             }
 
             #prepare_cached_impl
 
-            fn #execute_name(&mut self #params_declr) -> Result<u64, postgres::Error> {
-                self.execute(#query, #params_query_ref)
+            #cfg_attrs
+            fn #execute_name(&mut self #params_declr) -> Result<u64, #error_type> {
+                let count = self.execute(#query, #params_query_ref)?;
+                #check_affected
+                Ok(count)
             }
 
+            #cfg_attrs
             fn #execute_prepared_name(&mut self, stmt: &#Statement #params_declr)
-                -> Result<u64, postgres::Error>
+                -> Result<u64, #error_type>
             {
-                self.execute(&stmt.0, #params_query_ref)
+                let count = self.execute(&stmt.0, #params_query_ref)?;
+                #check_affected
+                Ok(count)
             }
 
-            fn #query_name(&mut self #params_declr) -> Result<Vec<(#outputs_declr)>, postgres::Error> {
-                let result: Result<Vec<_>, postgres::Error> =
+            #cfg_attrs
+            fn #query_name(&mut self #params_declr) -> Result<Vec<#row_type>, #row_error_type> {
+                let result: Result<Vec<_>, #row_error_type> =
                     self.query(#query, #params_query_ref)?.into_iter().map(#convert_row).collect();
                 result
             }
 
-            fn #query_one_name(&mut self #params_declr) -> Result<(#outputs_declr), postgres::Error> {
-                Ok(#convert_row(self.query_one(#query, #params_query_ref)?)?)
+            #cfg_attrs
+            fn #query_one_name(&mut self #params_declr) -> Result<#row_type, #row_error_type> {
+                #convert_row(self.query_one(#query, #params_query_ref)?)
+            }
+
+            #cfg_attrs
+            fn #query_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<Vec<#row_type>, #row_error_type> {
+                let result: Result<Vec<_>, #row_error_type> =
+                    self.query(&stmt.0, #params_query_ref)?.into_iter().map(#convert_row).collect();
+                result
+            }
+
+            #cfg_attrs
+            fn #query_one_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<#row_type, #row_error_type> {
+                #convert_row(self.query_one(&stmt.0, #params_query_ref)?)
+            }
+
+            #cfg_attrs
+            fn #query_opt_name(&mut self #params_declr) -> Result<Option<#row_type>, #row_error_type> {
+                match self.query_opt(#query, #params_query_ref)? {
+                    None => Ok(None),
+                    Some(x) => Ok(Some(#convert_row(x)?)),
+                }
+            }
+
+            #cfg_attrs
+            fn #query_opt_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<Option<#row_type>, #row_error_type> {
+                match self.query_opt(&stmt.0, #params_query_ref)? {
+                    None => Ok(None),
+                    Some(x) => Ok(Some(#convert_row(x)?)),
+                }
+            }
+        };
+
+        let (insert_many_decl, insert_many_impl) = self.insert_many();
+        let test_code = self.test_code();
+
+        Expansion {
+            decls: quote! { #decls #insert_many_decl },
+            impls: quote! { #timpl #insert_many_impl },
+            items: quote! {
+                #defs
+                #test_code
+            },
+        }
+    }
+
+    /// The `postgres` expansion of a dynamic query, which cannot be prepared ahead.
+    fn postgres_dynamic_expand(&self) -> Expansion {
+        let execute_name = self.prepend_name("execute_");
+        let convert_row = self.prepend_name("convert_row_");
+        let query_name = self.prepend_name("query_");
+        let query_one_name = self.prepend_name("query_one_");
+        let query_opt_name = self.prepend_name("query_opt_");
+        let params_declr = self.params_declr();
+        let row_type = self.row_type();
+        let row_struct_def = self.row_struct_def();
+        let row_value = self.outputs_row_value(&self.kind);
+        let build = self.build_sql(quote! {}, true);
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+        let method_attrs = quote! { #cfg_attrs #doc_attrs };
+        let error_type = self.error_type();
+        let row_error_type = self.row_error_type();
+        // The methods converting rows may fail both in the checks and in `FromRow`.
+        let query_error_type = if self.wraps_errors() { &error_type } else { &row_error_type };
+        let check_affected = self.check_affected(quote! { count });
+
+        let decls = quote! {
+            #method_attrs
+            fn #execute_name(&mut self #params_declr) -> Result<u64, #error_type>;
+            #method_attrs
+            fn #query_name(&mut self #params_declr) -> Result<Vec<#row_type>, #query_error_type>;
+            #method_attrs
+            fn #query_one_name(&mut self #params_declr) -> Result<#row_type, #query_error_type>;
+            #method_attrs
+            fn #query_opt_name(&mut self #params_declr) -> Result<Option<#row_type>, #query_error_type>;
+        };
+
+        let impls = quote! {
+            #cfg_attrs
+            #[allow(clippy::needless_question_mark)]
+            fn #execute_name(&mut self #params_declr) -> Result<u64, #error_type> {
+                #build
+                let count = self.execute(sql.text(), sql.params())?;
+                #check_affected
+                Ok(count)
             }
 
-            fn #query_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<Vec<(#outputs_declr)>, postgres::Error> {
-                let result: Result<Vec<_>, postgres::Error> =
-                    self.query(&stmt.0, #params_query_ref)?.into_iter().map(#convert_row).collect();
-                result
+            #cfg_attrs
+            #[allow(clippy::needless_question_mark)]
+            fn #query_name(&mut self #params_declr) -> Result<Vec<#row_type>, #query_error_type> {
+                #build
+                let rows = self.query(sql.text(), sql.params())?;
+                Ok(rows.into_iter().map(#convert_row).collect::<Result<_, _>>()?)
             }
 
-            fn #query_one_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<(#outputs_declr), postgres::Error> {
-                Ok(#convert_row(self.query_one(&stmt.0, #params_query_ref)?)?)
+            #cfg_attrs
+            #[allow(clippy::needless_question_mark)]
+            fn #query_one_name(&mut self #params_declr) -> Result<#row_type, #query_error_type> {
+                #build
+                Ok(#convert_row(self.query_one(sql.text(), sql.params())?)?)
             }
 
-            fn #query_opt_name(&mut self #params_declr) -> Result<Option<(#outputs_declr)>, postgres::Error> {
-                match self.query_opt(#query, #params_query_ref)? {
+            #cfg_attrs
+            fn #query_opt_name(&mut self #params_declr) -> Result<Option<#row_type>, #query_error_type> {
+                #build
+                match self.query_opt(sql.text(), sql.params())? {
                     None => Ok(None),
                     Some(x) => Ok(Some(#convert_row(x)?)),
                 }
             }
+        };
 
-            fn #query_opt_prepared_name(&mut self, stmt: &#Statement #params_declr) -> Result<Option<(#outputs_declr)>, postgres::Error> {
-                match self.query_opt(&stmt.0, #params_query_ref)? {
-                    None => Ok(None),
-                    Some(x) => Ok(Some(#convert_row(x)?)),
-                }
+        let test_code = self.test_code();
+        let items = quote! {
+            #row_struct_def
+
+            #cfg_attrs
+            #[allow(deprecated, clippy::needless_question_mark)]
+            pub fn #convert_row(row: postgres::Row) -> Result<#row_type, #row_error_type> {
+                Ok(#row_value)
             }
+
+            #test_code
         };
 
+        Expansion { decls, impls, items }
+    }
+
+    fn sqlite_expand(&self) -> Expansion {
+        if self.script {
+            return self.script_expand();
+        }
+        if let Some(name) = self.single_row_name() {
+            return self.single_row_expand(name);
+        }
+
+        #[allow(non_snake_case)]
+        let StatementType = self.instance_name("Statement_");
+        #[allow(non_snake_case)]
+        let CachedStatementType = self.prepend_name("CachedStatement_");
+        let prepare_name = self.prepend_name("prepare_");
+        let prepare_cached_name = self.prepend_name("prepare_cached_");
+        let execute_name = self.prepend_name("execute_");
+        let query_row_name = self.prepend_name("query_row_");
+        let params_declr = self.params_declr();
+        let row_args_declr = self.row_args_declr();
+        let params_query = self.params_query();
+        let params_relay = self.params_relay();
+        let query = &self.query;
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+        let method_attrs = quote! { #cfg_attrs #doc_attrs };
+        let error_type = self.error_type();
+
         let test_code = self.test_code();
 
-        quote! {
-            #defs
+        if self.is_dynamic() {
+            return self.sqlite_dynamic_expand(test_code);
+        }
+
+        let (execute_type, execute_body) = if self.is_returning() {
+            #[allow(non_snake_case)]
+            let Rows = self.prepend_name("Rows_");
+            let row_type = self.row_type();
+            let check_affected = self.check_affected(quote! { rows.len() });
+            (quote! { Vec<#row_type> }, quote! {
+                let mut stmt = self.prepare_cached(#query)?;
+                let rows = #Rows::new(stmt.query(#params_query)?).collect::<rusqlite::Result<Vec<_>>>()?;
+                #check_affected
+                Ok(rows)
+            })
+        } else {
+            let check_affected = self.check_affected(quote! { count });
+            (quote! { usize }, quote! {
+                let count = self.execute(#query, #params_query)?;
+                #check_affected
+                Ok(count)
+            })
+        };
 
-            impl #Client for postgres::Client {
-                #timpl
+        let mut decls = quote! {
+            #method_attrs
+            fn #prepare_name(&self) -> rusqlite::Result<#StatementType<'_>>;
+            #method_attrs
+            fn #prepare_cached_name(&self) -> rusqlite::Result<#CachedStatementType<'_>>;
+            #method_attrs
+            fn #execute_name(&self #params_declr) -> Result<#execute_type, #error_type>;
+            #method_attrs
+            fn #query_row_name<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<T>
+            where
+                F: FnMut(#row_args_declr) -> T;
+        };
+
+        let mut impls = quote! {
+            #cfg_attrs
+            fn #prepare_name(&self) -> rusqlite::Result<#StatementType<'_>> {
+                self.prepare(#query).map(#StatementType)
             }
 
-            impl<'a> #Client for postgres::Transaction<'a> {
-                #timpl
+            #cfg_attrs
+            fn #prepare_cached_name(&self) -> rusqlite::Result<#CachedStatementType<'_>> {
+                self.prepare_cached(#query).map(#CachedStatementType)
             }
 
-            #test_code
+            #cfg_attrs
+            fn #execute_name(&self #params_declr) -> Result<#execute_type, #error_type> {
+                #execute_body
+            }
+
+            #cfg_attrs
+            fn #query_row_name<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<T>
+            where
+                F: FnMut(#row_args_declr) -> T,
+            {
+                let mut stmt = self.#prepare_name()?;
+                stmt.query_row(#params_relay f)
+            }
+        };
+
+        if let Some(rowid) = &self.rowid {
+            let insert_name = self.prepend_name("execute_rowid_");
+            decls.extend(quote! {
+                #method_attrs
+                fn #insert_name(&self #params_declr) -> rusqlite::Result<#rowid>;
+            });
+            impls.extend(quote! {
+                #cfg_attrs
+                #[allow(clippy::useless_conversion)]
+                fn #insert_name(&self #params_declr) -> rusqlite::Result<#rowid> {
+                    let rowid = self.prepare_cached(#query)?.insert(#params_query)?;
+                    Ok(rowid.into())
+                }
+            });
         }
+
+        let (decl, imp) = self.insert_many();
+        decls.extend(decl);
+        impls.extend(imp);
+
+        let items = self.sqlite_items(Some((quote! {}, params_query)), test_code);
+
+        Expansion { decls, impls, items }
     }
 
-    fn sqlite_expand(&self) -> Tokens {
-        let conn_trait_name = self.prepend_name("Connection_");
+    /// The `rusqlite` items used by the methods of the extension trait. Given `binds`,
+    /// the statement types are generated as well, which bind their parameters with
+    /// `bind_params` after `bind_prelude`.
+    fn sqlite_items(&self, binds: Option<(Tokens, Tokens)>, test_code: Tokens) -> Tokens {
         #[allow(non_snake_case)]
-        let StatementType = self.prepend_name("Statement_");
+        let StatementType = self.instance_name("Statement_");
         #[allow(non_snake_case)]
         let CachedStatementType = self.prepend_name("CachedStatement_");
         #[allow(non_snake_case)]
         let MappedRows = self.prepend_name("MappedRows_");
         #[allow(non_snake_case)]
         let Rows = self.prepend_name("Rows_");
-        let prepare_name = self.prepend_name("prepare_");
-        let prepare_cached_name = self.prepend_name("prepare_cached_");
-        let execute_name = self.prepend_name("execute_");
-        let query_row_name = self.prepend_name("query_row_");
         let params_declr = self.params_declr();
-        let outputs_declr = self.outputs_declr();
-        let row_closure = self.outputs_row_get_numbered();
+        let row_type = self.row_type();
+        let row_args_declr = self.row_args_declr();
+        let row_struct_def = self.row_struct_def();
+        let row_value = self.outputs_row_value(&self.kind);
         let mapped_row_closure = self.outputs_mapped_row_closure();
-        let params_query = self.params_query();
         let params_relay = self.params_relay();
-        let query = &self.query;
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+        let execute_error_type = match self.expect_rows {
+            Some(_) => quote! { fnsql::Error<rusqlite::Error> },
+            None => quote! { rusqlite::Error },
+        };
+        let execute_type = if self.is_returning() {
+            quote! { Vec<#row_type> }
+        } else {
+            quote! { () }
+        };
 
-        let test_code = self.test_code();
+        let statements = match binds {
+            None => quote! {},
+            Some((bind_prelude, bind_params)) => {
+                let execute = if self.is_returning() {
+                    let check_affected = self.check_affected(quote! { rows.len() });
+                    quote! {
+                        let rows = #Rows::new(self.0.query(#bind_params)?)
+                            .collect::<rusqlite::Result<Vec<_>>>()?;
+                        #check_affected
+                        Ok(rows)
+                    }
+                } else if self.expect_rows.is_some() {
+                    let check_affected = self.check_affected(quote! { count });
+                    quote! {
+                        let count = self.0.execute(#bind_params)?;
+                        #check_affected
+                        Ok(())
+                    }
+                } else {
+                    quote! {
+                        self.0.execute(#bind_params)?;
+                        Ok(())
+                    }
+                };
+                // Only static queries have a `rowid`, so there is no prelude.
+                let insert = match &self.rowid {
+                    Some(rowid) => quote! {
+                        #[allow(clippy::useless_conversion)]
+                        pub fn insert(&mut self #params_declr) -> rusqlite::Result<#rowid> {
+                            Ok(self.0.insert(#bind_params)?.into())
+                        }
+                    },
+                    None => quote! {},
+                };
+                quote! {
+                    #cfg_attrs
+                    #doc_attrs
+                    #[allow(non_camel_case_types)]
+                    pub struct #StatementType<'a>(pub rusqlite::Statement<'a>);
+
+                    #cfg_attrs
+                    #[allow(deprecated, clippy::ptr_arg)]
+                    impl<'a> #StatementType<'a> {
+                        pub fn query_map<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<#MappedRows<'_, F>>
+                        where
+                            F: FnMut(#row_args_declr) -> T,
+                        {
+                            #bind_prelude
+                            let rows = self.0.query(#bind_params)?;
+                            Ok(#MappedRows::new(rows, f))
+                        }
 
-        quote! {
-            #[allow(non_camel_case_types)]
-            pub trait #conn_trait_name {
-                fn #prepare_name(&self) -> rusqlite::Result<#StatementType<'_>>;
-                fn #prepare_cached_name(&self) -> rusqlite::Result<#CachedStatementType<'_>>;
-                fn #execute_name(&self #params_declr) -> rusqlite::Result<usize>;
-                fn #query_row_name<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<T>
-                where
-                    F: FnMut(#outputs_declr) -> T;
-            }
-
-            impl #conn_trait_name for rusqlite::Connection {
-                fn #prepare_name(&self) -> rusqlite::Result<#StatementType<'_>> {
-                    self.prepare(#query).map(#StatementType)
-                }
+                        pub fn query_row<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<T>
+                        where
+                            F: FnMut(#row_args_declr) -> T,
+                        {
+                            let mut rows = self.query_map(#params_relay f)?;
+                            rows.next().unwrap_or(Err(rusqlite::Error::QueryReturnedNoRows))
+                        }
 
-                fn #prepare_cached_name(&self) -> rusqlite::Result<#CachedStatementType<'_>> {
-                    self.prepare_cached(#query).map(#CachedStatementType)
-                }
+                        pub fn query(&mut self #params_declr) -> rusqlite::Result<#Rows<'_>> {
+                            #bind_prelude
+                            let rows = self.0.query(#bind_params)?;
+                            Ok(#Rows::new(rows))
+                        }
 
-                fn #execute_name(&self #params_declr) -> rusqlite::Result<usize> {
-                    self.execute(#query, #params_query)
-                }
+                        pub fn execute(&mut self #params_declr) -> Result<#execute_type, #execute_error_type> {
+                            #bind_prelude
+                            #execute
+                        }
 
-                fn #query_row_name<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<T>
-                where
-                    F: FnMut(#outputs_declr) -> T,
-                {
-                    let mut stmt = self.#prepare_name()?;
-                    stmt.query_row(#params_relay f)
+                        #insert
+                    }
+
+                    #cfg_attrs
+                    #doc_attrs
+                    #[allow(non_camel_case_types)]
+                    pub struct #CachedStatementType<'a>(pub rusqlite::CachedStatement<'a>);
+
+                    #cfg_attrs
+                    #[allow(deprecated, clippy::ptr_arg)]
+                    impl<'a> #CachedStatementType<'a> {
+                        pub fn query_map<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<#MappedRows<'_, F>>
+                        where
+                            F: FnMut(#row_args_declr) -> T,
+                        {
+                            #bind_prelude
+                            let rows = self.0.query(#bind_params)?;
+                            Ok(#MappedRows::new(rows, f))
+                        }
+
+                        pub fn query_row<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<T>
+                        where
+                            F: FnMut(#row_args_declr) -> T,
+                        {
+                            let mut rows = self.query_map(#params_relay f)?;
+                            rows.next().unwrap_or(Err(rusqlite::Error::QueryReturnedNoRows))
+                        }
+
+                        pub fn query(&mut self #params_declr) -> rusqlite::Result<#Rows<'_>> {
+                            #bind_prelude
+                            let rows = self.0.query(#bind_params)?;
+                            Ok(#Rows::new(rows))
+                        }
+
+                        pub fn execute(&mut self #params_declr) -> Result<#execute_type, #execute_error_type> {
+                            #bind_prelude
+                            #execute
+                        }
+
+                        #insert
+                    }
                 }
             }
+        };
+
+        quote! {
+            #row_struct_def
 
+            #cfg_attrs
             #[allow(non_camel_case_types)]
             pub struct #MappedRows<'stmt, F> {
                 rows: rusqlite::Rows<'stmt>,
                 map: F,
             }
 
+            #cfg_attrs
             impl<'stmt, T, F> #MappedRows<'stmt, F>
             where
-                F: FnMut(#outputs_declr) -> T
+                F: FnMut(#row_args_declr) -> T
             {
                 pub(crate) fn new(rows: rusqlite::Rows<'stmt>, f: F) -> Self {
                     Self { rows, map: f }
                 }
             }
 
+            #cfg_attrs
+            #[allow(deprecated)]
             impl<'stmt, T, F> Iterator for #MappedRows<'stmt, F>
             where
-                F: FnMut(#outputs_declr) -> T
+                F: FnMut(#row_args_declr) -> T
             {
                 type Item = rusqlite::Result<T>;
 
@@ -606,19 +2119,23 @@ impl Query {
                 }
             }
 
+            #cfg_attrs
             #[allow(non_camel_case_types)]
             pub struct #Rows<'stmt> {
                 rows: rusqlite::Rows<'stmt>,
             }
 
+            #cfg_attrs
             impl<'stmt> #Rows<'stmt> {
                 pub(crate) fn new(rows: rusqlite::Rows<'stmt>) -> Self {
                     Self { rows }
                 }
             }
 
+            #cfg_attrs
+            #[allow(deprecated, clippy::needless_question_mark)]
             impl<'stmt> Iterator for #Rows<'stmt> {
-                type Item = rusqlite::Result<(#outputs_declr)>;
+                type Item = rusqlite::Result<#row_type>;
 
                 fn next(&mut self) -> Option<Self::Item> {
                     self.rows
@@ -626,89 +2143,253 @@ impl Query {
                         .transpose()
                         .map(|row_result| {
                             row_result.and_then(|row| {
-                                Ok((#row_closure))
+                                Ok(#row_value)
                             })
                         })
                 }
             }
 
-            #[allow(non_camel_case_types)]
-            pub struct #StatementType<'a>(pub rusqlite::Statement<'a>);
+            #statements
 
-            impl<'a> #StatementType<'a> {
-                fn query_map<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<#MappedRows<'_, F>>
-                where
-                    F: FnMut(#outputs_declr) -> T,
-                {
-                    let rows = self.0.query(#params_query)?;
-                    Ok(#MappedRows::new(rows, f))
-                }
+            #test_code
+        }
+    }
 
-                fn query_row<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<T>
-                where
-                    F: FnMut(#outputs_declr) -> T,
-                {
-                    let rows = self.query_map(#params_relay f)?;
-                    for item in rows {
-                        return Ok(item?);
+    /// Whether a list that exceeds the SQLite limit on placeholders can be split,
+    /// executing the query once per part, which is when that gives the same result:
+    /// the list is only used by `IN` conditions that are joined to the rest of the
+    /// `WHERE` clause by `AND`, as each part of a `NOT IN` would match the items of
+    /// the others, and an `OR` would match the same rows for every part, and the
+    /// query does not combine rows, e.g. by an aggregate, `ORDER BY` or `LIMIT`.
+    fn splits_list(&self, list: &Param) -> bool {
+        let sql = self.query.value();
+        let mut uses = sql::placeholders(&sql).into_iter().filter(|(_, placeholder)| {
+            matches!(placeholder, sql::Placeholder::Named(name) if list.name == name)
+        });
+        uses.all(|(range, _)| match sql::in_list(&sql, range) {
+            Some((in_range, false)) => sql::is_where_conjunct(&sql, in_range),
+            _ => false,
+        }) && !sql::combines_rows(&sql)
+    }
+
+    /// Builds the SQL of a dynamic `rusqlite` query into `sql` for `body`. If the list
+    /// exceeds the SQLite limit on placeholders and `splits_list()`, `body` is repeated
+    /// for each chunk of it, within a savepoint. `body` must not return.
+    fn sqlite_for_each_chunk(&self, body: Tokens) -> Tokens {
+        let chunk = Ident::new("chunk", self.name.span());
+        let build_chunk = self.build_sql(quote! { #chunk }, true);
+        let list = match self.list_param() {
+            Some(list) if self.splits_list(list) => list,
+            Some(list) => {
+                let list_name = &list.name;
+                return quote! {
+                    let #chunk = #list_name;
+                    #build_chunk
+                    #body
+                };
+            }
+            None => {
+                return quote! {
+                    #build_chunk
+                    #body
+                };
+            }
+        };
+
+        // The savepoint is handled by statements, as `rusqlite` only takes a savepoint
+        // of a mutable connection.
+        let list_name = &list.name;
+        let fixed = self.sqlite_fixed_placeholders();
+        quote! {
+            let size = fnsql::builder::SQLITE_MAX_VARIABLES.saturating_sub(#fixed);
+            let split = #list_name.len() > size;
+            if split {
+                self.execute_batch("SAVEPOINT fnsql_chunks")?;
+            }
+            #[allow(clippy::redundant_closure_call)]
+            let result = (|| -> rusqlite::Result<()> {
+                for #chunk in fnsql::builder::chunks(#list_name, size) {
+                    #build_chunk
+                    #body
+                }
+                Ok(())
+            })();
+            if split {
+                match result {
+                    Ok(()) => self.execute_batch("RELEASE fnsql_chunks")?,
+                    Err(_) => {
+                        let _ = self.execute_batch(
+                            "ROLLBACK TO fnsql_chunks; RELEASE fnsql_chunks");
                     }
-                    Err(rusqlite::Error::QueryReturnedNoRows)
                 }
+            }
+            result?;
+        }
+    }
 
-                fn query(&mut self #params_declr) -> rusqlite::Result<#Rows<'_>> {
-                    let rows = self.0.query(#params_query)?;
-                    Ok(#Rows::new(rows))
-                }
+    /// The `rusqlite` expansion of a dynamic query. Lists that exceed the SQLite limit
+    /// on placeholders are executed in chunks where possible. Statements can be
+    /// prepared only for queries without optional fragments, given the length of the
+    /// list.
+    fn sqlite_dynamic_expand(&self, test_code: Tokens) -> Expansion {
+        #[allow(non_snake_case)]
+        let StatementType = self.instance_name("Statement_");
+        #[allow(non_snake_case)]
+        let CachedStatementType = self.prepend_name("CachedStatement_");
+        #[allow(non_snake_case)]
+        let MappedRows = self.prepend_name("MappedRows_");
+        #[allow(non_snake_case)]
+        let Rows = self.prepend_name("Rows_");
+        let prepare_name = self.prepend_name("prepare_");
+        let prepare_cached_name = self.prepend_name("prepare_cached_");
+        let execute_name = self.prepend_name("execute_");
+        let query_name = self.prepend_name("query_");
+        let query_row_name = self.prepend_name("query_row_");
+        let params_declr = self.params_declr();
+        let row_type = self.row_type();
+        let row_args_declr = self.row_args_declr();
+        let cfg_attrs = self.cfg_attrs();
+        let doc_attrs = self.doc_attrs();
+        let method_attrs = quote! { #cfg_attrs #doc_attrs };
+        let error_type = self.error_type();
+        let no_rows = self.driver_error(quote! { rusqlite::Error::QueryReturnedNoRows });
+
+        let for_each_chunk = |body| self.sqlite_for_each_chunk(body);
+        let query_body = for_each_chunk(quote! {
+            let mut stmt = self.prepare_cached(sql.text())?;
+            for row in #Rows::new(stmt.query(sql.params())?) {
+                rows.push(row?);
+            }
+        });
+        let (execute_type, execute_body) = if self.is_returning() {
+            let check_affected = self.check_affected(quote! { rows.len() });
+            (quote! { Vec<#row_type> }, quote! {
+                let mut rows = vec![];
+                #query_body
+                #check_affected
+                Ok(rows)
+            })
+        } else {
+            let check_affected = self.check_affected(quote! { count });
+            let execute_body = for_each_chunk(quote! {
+                count += self.prepare_cached(sql.text())?.execute(sql.params())?;
+            });
+            (quote! { usize }, quote! {
+                let mut count = 0;
+                #execute_body
+                #check_affected
+                Ok(count)
+            })
+        };
+        let query_row_body = for_each_chunk(quote! {
+            if first.is_none() {
+                let mut stmt = self.prepare_cached(sql.text())?;
+                first = #MappedRows::new(stmt.query(sql.params())?, &mut f).next().transpose()?;
+            }
+        });
+
+        let mut decls = quote! {
+            #method_attrs
+            fn #execute_name(&self #params_declr) -> Result<#execute_type, #error_type>;
+            #method_attrs
+            fn #query_name(&self #params_declr) -> Result<Vec<#row_type>, #error_type>;
+            #method_attrs
+            fn #query_row_name<F, T>(&mut self #params_declr, f: F) -> Result<T, #error_type>
+            where
+                F: FnMut(#row_args_declr) -> T;
+        };
 
-                fn execute(&mut self #params_declr) -> rusqlite::Result<()> {
-                    self.0.execute(#params_query)?;
-                    Ok(())
-                }
+        let mut impls = quote! {
+            #cfg_attrs
+            fn #execute_name(&self #params_declr) -> Result<#execute_type, #error_type> {
+                #execute_body
             }
 
-            #[allow(non_camel_case_types)]
-            pub struct #CachedStatementType<'a>(pub rusqlite::CachedStatement<'a>);
+            #cfg_attrs
+            fn #query_name(&self #params_declr) -> Result<Vec<#row_type>, #error_type> {
+                let mut rows = vec![];
+                #query_body
+                Ok(rows)
+            }
 
-            impl<'a> #CachedStatementType<'a> {
-                fn query_map<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<#MappedRows<'_, F>>
-                where
-                    F: FnMut(#outputs_declr) -> T,
-                {
-                    let rows = self.0.query(#params_query)?;
-                    Ok(#MappedRows::new(rows, f))
-                }
+            #cfg_attrs
+            fn #query_row_name<F, T>(&mut self #params_declr, mut f: F) -> Result<T, #error_type>
+            where
+                F: FnMut(#row_args_declr) -> T,
+            {
+                let mut first = None;
+                #query_row_body
+                first.ok_or(#no_rows)
+            }
+        };
 
-                fn query_row<F, T>(&mut self #params_declr, f: F) -> rusqlite::Result<T>
-                where
-                    F: FnMut(#outputs_declr) -> T,
-                {
-                    let rows = self.query_map(#params_relay f)?;
-                    for item in rows {
-                        return Ok(item?);
-                    }
-                    Err(rusqlite::Error::QueryReturnedNoRows)
-                }
+        let list = match self.list_param() {
+            Some(list) if self.fragments().is_empty() && !self.update => list,
+            _ => {
+                let items = self.sqlite_items(None, test_code);
+                return Expansion { decls, impls, items };
+            }
+        };
 
-                fn query(&mut self #params_declr) -> rusqlite::Result<#Rows<'_>> {
-                    let rows = self.0.query(#params_query)?;
-                    Ok(#Rows::new(rows))
-                }
+        let list_name = &list.name;
+        let list_len = Ident::new(&format!("{}_len", list_name), list_name.span());
+        let build_prepared = self.build_sql(quote! { #list_len }, false);
+
+        decls.extend(quote! {
+            #method_attrs
+            fn #prepare_name(&self, #list_len: usize) -> rusqlite::Result<#StatementType<'_>>;
+            #method_attrs
+            fn #prepare_cached_name(&self, #list_len: usize) -> rusqlite::Result<#CachedStatementType<'_>>;
+        });
+
+        impls.extend(quote! {
+            #cfg_attrs
+            fn #prepare_name(&self, #list_len: usize) -> rusqlite::Result<#StatementType<'_>> {
+                #build_prepared
+                self.prepare(sql.text()).map(#StatementType)
+            }
 
-                fn execute(&mut self #params_declr) -> rusqlite::Result<()> {
-                    self.0.execute(#params_query)?;
-                    Ok(())
-                }
+            #cfg_attrs
+            fn #prepare_cached_name(&self, #list_len: usize) -> rusqlite::Result<#CachedStatementType<'_>> {
+                #build_prepared
+                self.prepare_cached(sql.text()).map(#CachedStatementType)
             }
+        });
 
-            #test_code
-        }
+        let build = self.build_sql(quote! { #list_name }, true);
+        let items = self.sqlite_items(Some((build, quote! { sql.params() })), test_code);
+
+        Expansion { decls, impls, items }
     }
 
     fn test_code(&self) -> Tokens {
-        let test_name = self.prepend_name("auto_");
-        let testsetup_name = self.prepend_name("testsetup_");
+        let test_name = self.instance_name("auto_");
+        let testsetup_name = self.testsetup_name(&self.name);
         let (params_arbit_prep, params_arbit) = self.params_arbitrary();
-        let execute_name = self.prepend_name("execute_");
+        let execute_name = match self.single_row_name() {
+            Some(name) => name,
+            None if self.script => self.prepend_name("execute_batch_"),
+            None if self.channel.is_some() => self.prepend_name("notify_"),
+            None => self.prepend_name("execute_"),
+        };
+        let call = match self.copy {
+            None => quote! { conn.#execute_name(#params_arbit) },
+            Some(CopyDirection::In) => {
+                let copy_in_name = self.prepend_name("copy_in_");
+                quote! {
+                    conn.#copy_in_name().and_then(|mut writer| {
+                        writer.write(#params_arbit)?;
+                        writer.finish()
+                    })
+                }
+            }
+            Some(CopyDirection::Out) => {
+                let copy_out_name = self.prepend_name("copy_out_");
+                quote! {
+                    conn.#copy_out_name().and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+                }
+            }
+        };
         let name = syn::LitStr::new(&self.name.to_string(), self.name.span());
 
         let client_type = match self.kind {
@@ -723,10 +2404,7 @@ impl Query {
             Kind::Rusqlite => quote!{Err(rusqlite::Error::ExecuteReturnedResults) => {}},
             Kind::PostgreSQL => quote!{},
         };
-        let error_type = match self.kind {
-            Kind::Rusqlite => quote!{rusqlite::Error},
-            Kind::PostgreSQL => quote!{postgres::Error},
-        };
+        let error_type = self.driver_error_type();
         let open_client = match self.kind {
             Kind::Rusqlite => quote!{
                 let conn = #client_type::open_in_memory()?;
@@ -738,16 +2416,33 @@ impl Query {
             }; },
         };
 
+        let cfg_attrs = self.cfg_attrs();
+
+        // Only errors of the driver fail the test, as the checks of `fnsql::Error`
+        // depend on the arbitrary arguments and the rows.
+        let driver_result = if self.wraps_errors() {
+            quote! {
+                let r = match r {
+                    Err(fnsql::Error::Driver(err)) => Err(err),
+                    _ => Ok(()),
+                };
+            }
+        } else {
+            quote! {}
+        };
+
         let test = if let Some(depends) = &self.test {
             let depends = depends.iter().map(|name| {
                 let parent_testsetup_name =
-                    Ident::new(&format!("testsetup_{}", name), self.name.span());
+                    self.testsetup_name(&Ident::new(name, self.name.span()));
                 quote! {
                     #parent_testsetup_name(uns, deps, conn)?;
                 }
             });
             quote! {
                 #[cfg(test)]
+                #cfg_attrs
+                #[allow(deprecated)]
                 fn #testsetup_name(
                     uns: &mut arbitrary::Unstructured,
                     deps: &mut std::collections::HashSet<&'static str>,
@@ -760,7 +2455,8 @@ impl Query {
                     #(#depends);*
 
                     #params_arbit_prep;
-                    let r = conn.#execute_name(#params_arbit);
+                    let r = #call;
+                    #driver_result
                     match r {
                         Ok(_) => {}
                         #ignore_error
@@ -773,6 +2469,7 @@ impl Query {
                 }
 
                 #[test]
+                #cfg_attrs
                 fn #test_name() -> Result<(), #error_type> {
                     #open_client;
                     let mut deps = std::collections::HashSet::new();
@@ -790,18 +2487,6 @@ impl Query {
     }
 }
 
-struct Output {
-    ttype: syn::Type,
-}
-
-impl Parse for Output {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let ttype = input.parse()?;
-
-        Ok(Self { ttype })
-    }
-}
-
 impl Output {
     fn expand_declr(&self) -> Tokens {
         let ttype = &self.ttype;
@@ -810,21 +2495,6 @@ impl Output {
     }
 }
 
-struct Param {
-    name: Ident,
-    ttype: syn::Type,
-}
-
-impl Parse for Param {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let name = input.parse()?;
-        let _: Token![:] = input.parse()?;
-        let ttype = input.parse()?;
-
-        Ok(Self { name, ttype })
-    }
-}
-
 impl Param {
     fn expand_declr(&self) -> Tokens {
         let name = &self.name;
@@ -833,74 +2503,40 @@ impl Param {
         quote! { #name: &#ttype }
     }
 
-    fn expand_query(&self, query: &Query) -> Tokens {
-        let name = &self.name;
-        let specifier = syn::LitStr::new(&format!(":{}", name), name.span());
-
-        match query.kind {
-            Kind::Rusqlite => quote! { (#specifier, &#name as &dyn rusqlite::ToSql) },
-            Kind::PostgreSQL => quote! { &#name as &(dyn postgres::types::ToSql + Sync) }
+    /// The owned counterpart of the type, e.g. `String` for `str`.
+    fn owned_type(&self) -> Tokens {
+        let ttype = &self.ttype;
+        if ttype.to_token_stream().to_string() == "str" {
+            quote! {String}
+        } else if let syn::Type::Slice(slice) = ttype {
+            let elem = &slice.elem;
+            quote! {Vec<#elem>}
+        } else {
+            quote! {#ttype}
         }
     }
-}
 
-enum Attr {
-    Kind(Kind),
-    Test(Vec<TestAttr>),
-    Named,
 }
 
-impl Parse for Attr {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let ident: Ident = input.parse()?;
-        if ident == "rusqlite" {
-            return Ok(Attr::Kind(Kind::Rusqlite));
-        }
-        if ident == "postgres" {
-            return Ok(Attr::Kind(Kind::PostgreSQL));
-        }
-        if ident == "named" {
-            return Ok(Attr::Named);
-        }
-        if ident == "test" {
-            let mut v = vec![];
-
-            if input.peek(token::Paren) {
-                let content;
-                let _ = parenthesized!(content in input);
-                let list: Punctuated<TestAttr, Token![,]> =
-                    content.parse_terminated(Parse::parse)?;
-                v = list.into_iter().collect();
-            };
-
-            return Ok(Attr::Test(v));
+impl Bind<'_> {
+    /// The bound value, e.g. `pet.id` for a field of a struct parameter.
+    fn value(&self) -> Tokens {
+        let name = &self.param.name;
+        match self.field {
+            None => quote! { #name },
+            Some(field) => quote! { #name.#field },
         }
-        panic!("unknown attribute {}", ident);
     }
-}
-
-enum TestAttr {
-    With(Vec<String>),
-}
-
-impl Parse for TestAttr {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let ident: Ident = input.parse()?;
-        if ident == "with" {
-            let mut v = vec![];
 
-            let _: Token![=] = input.parse()?;
-            let content;
-            let _ = bracketed!(content in input);
-            let list: Punctuated<Ident, Token![,]> = content.parse_terminated(Parse::parse)?;
-            for item in list {
-                v.push(item.to_string());
-            }
+    fn expand_query(&self, query: &Query) -> Tokens {
+        let name = self.name();
+        let value = self.value();
+        let specifier = syn::LitStr::new(&format!(":{}", name), name.span());
 
-            return Ok(TestAttr::With(v));
+        match query.kind {
+            Kind::Rusqlite => quote! { (#specifier, &#value as &dyn rusqlite::ToSql) },
+            Kind::PostgreSQL => quote! { &#value as &(dyn postgres::types::ToSql + Sync) }
         }
-
-        panic!("unknown test attribute {}", ident);
     }
 }
 
@@ -908,30 +2544,237 @@ impl Parse for TestAttr {
 ///
 /// ```ignore
 /// fnsql! {
+///     [OPTIONAL: mod <module-name> {]
+///     [OPTIONAL: #![<block-level default attributes>]]
+///
+///     [OPTIONAL: /// <doc comment>, #[<other Rust attributes>]]
 ///     #[<sql-engine-type>, [OPTIONAL: test(with=[other-function-a, other-function-b...])]]
-///     <function-name-a>(param1: type, param2: type...)
-///          [OPTIONAL: -> [(col a type, col b type, ...)]]
+///     <function-name-a>(param1: type, param2: type [OPTIONAL: as list | { field, ... }]...)
+///          [OPTIONAL: -> [([col-a-name:] col a type, [col-b-name:] col b type, ...)] | [row-type]
+///                      | ([col-a-name:] col a type, ...) | Option<(...)> | col-type]
 ///     {
 ///         "SQL QUERY STRING"
 ///     }
 ///
 ///     ...
+///     [OPTIONAL: }]
 /// }
 /// ```
 ///
 /// **For examples see the root doc of the `fnsql` crate.**
 ///
 /// - Return type is optional, and only meaningful for SQL operations that return row data.
+/// - Output columns can be named, e.g. `-> [(id: i32, name: String)]`. In that case a
+///   `Row_<function-name>` struct with these fields is generated, and returned instead of a
+///   tuple. Adding the `serde` attribute also derives `serde::Serialize` and
+///   `serde::Deserialize` for it.
+/// - The output can also be a user type implementing `fnsql::FromRow`, e.g. `-> [Pet]`, which
+///   is usually done with `#[derive(fnsql::FromRow)]` and `#[fnsql(rusqlite)]`. The `postgres`
+///   methods returning it return `fnsql::Error`, which is `fnsql::Error::Row` for errors of the
+///   implementation other than `postgres::Error`. The `rusqlite` methods return such errors as
+///   `rusqlite::Error::UserFunctionError`, which needs the `functions` feature of `rusqlite`.
+/// - Without the brackets, the query returns a single row: `-> (i32, String)` generates only
+///   `query_one_<function-name>`, `-> Option<(i32, String)>` only `query_opt_<function-name>`,
+///   and a single type, e.g. `-> i64`, only `query_scalar_<function-name>`. These fail with
+///   `fnsql::Error::NoRows` or `fnsql::Error::MultipleRows` on other numbers of rows. A single
+///   type is always a column, so a `FromRow` type is only supported as rows, e.g. `-> [Pet]`.
 /// - sql-engine-type: supported backends: `rusqlite` and `postgres`.
+/// - A query can be given both backends, e.g. `#[rusqlite, postgres]`, in which case its
+///   placeholders are written as ':name', and translated to '$1', '$2', etc. for `postgres`.
+///   Both extension traits are generated, and both tests, which are named after the
+///   backend, e.g. `auto_postgres_<function-name>`. So are the statement types, while a
+///   `Row_<function-name>` struct is shared.
+/// - Instead of a single SQL string, the body can hold SQL per backend, e.g.
+///   `{ rusqlite: "...", postgres: "..." }`, sharing the parameters and outputs. Unless given
+///   explicitly, the backends of the query are those of its variants, each with its own test.
+/// - A parameter can be a list, e.g. `ids: [i64] as list`, which is used as `IN (:ids)`. A
+///   query can have one list parameter. For `rusqlite`, the SQL is built for the length of
+///   the list, which `prepare_<function-name>` and `prepare_cached_<function-name>` then
+///   take, and a `query_<function-name>` returning all the rows is generated as well.
+/// - A bracketed part of the SQL that uses an `Option` parameter, e.g. `[AND name = :name]`,
+///   is included only when the parameter is `Some`. Fragments cannot be nested, and only
+///   start a word, so that `ARRAY[...]` and subscripts are left as they are. Queries with
+///   fragments have no `prepare_` methods.
+/// - With the `update` attribute, the assignments in the `SET` clause of an `UPDATE` that use
+///   `Option` parameters are included only when these are `Some`. The methods then return
+///   `fnsql::Error`, with `fnsql::Error::NoAssignments` when no assignment is included.
+/// - With `expect_rows = 1` or `expect_rows = 0..=1`, the execute methods fail with
+///   `fnsql::Error::AffectedRows` when the query affects a number of rows out of this range.
+/// - With the `script` attribute, the SQL can have several statements, which
+///   `execute_batch_<function-name>` executes together. Scripts have no parameters or outputs.
+/// - For `rusqlite`, the `rowid` attribute adds `execute_rowid_<function-name>`, returning the
+///   row id of the inserted row, which can be typed with e.g. `rowid = PetId` for an id type
+///   implementing `From<i64>`. With outputs and a `RETURNING` clause, `execute_<function-name>`
+///   returns the rows.
+/// - A parameter can list fields of its struct, e.g. `pet: &Pet { id, name }`, which are bound
+///   to the placeholders named after them, or numbered in order for positional placeholders.
+/// - The `many` attribute adds `insert_many_<function-name>`, which executes the query for
+///   each item of an iterator within a savepoint or a transaction, and returns the total
+///   number of affected rows. Items are tuples of the parameters, or a single parameter.
+/// - For `postgres`, the `copy_in` attribute makes a binary `COPY ... FROM STDIN` of the
+///   parameters, with `copy_in_<function-name>` returning a `CopyIn_<function-name>` writer,
+///   and `copy_out` a binary `COPY ... TO STDOUT` of the outputs, with
+///   `copy_out_<function-name>` returning a `CopyOut_<function-name>` row iterator.
+/// - For `postgres`, the `channel` attribute declares a notification channel with a single
+///   payload parameter, e.g. `pet_changed(event: PetEvent);`, which needs no body unless the
+///   channel is named differently. It adds `listen_`, `unlisten_` and `notify_` methods, and a
+///   `Notifications_<function-name>` iterator wrapper. The payload is plain text, or JSON
+///   with `channel = json`.
 /// - Testing is optional - you have to specific the `test` attribute for it.
 /// - With `test(with=[...])`, you specify the quries that need execution for this
 ///   query to work.
+/// - A leading `#![...]` gives the backend, `named` and `test` attributes to all the queries
+///   of the block, e.g. `#![rusqlite, test(with=[create_table_pet])]`. A query's own
+///   backend attribute overrides the default one, and its `test(with=[...])` adds to the
+///   default dependencies, while `test(with=[])` clears them. A query that depends on
+///   itself is an error, so the query given in the defaults needs `test(with=[])`.
+/// - Doc comments and other Rust attributes of a query, e.g. `#[deprecated]`, are forwarded
+///   to the generated trait, its methods and the statement and row types. `#[cfg(...)]` is
+///   forwarded to all of the generated items, so that a query can be conditionally compiled.
+///   `#[serde(...)]` is forwarded as well, unlike the bare `#[serde]` of fnsql, while a single
+///   name close to one of the fnsql attributes, e.g. `#[rusqlit]`, is reported as unknown.
+/// - With the `mod <module-name> { ... }` wrapper, all the generated items are placed in that
+///   module, and the methods of all the queries are in a single `<module-name>::Queries`
+///   extension trait, or in `RusqliteQueries` and `PostgresQueries` if backends are mixed.
+/// - Named placeholders, e.g. ':name', are checked at compile time against the declared
+///   parameters: each placeholder needs a parameter, and each parameter needs to be used.
+/// - Likewise, positional placeholders of `postgres` queries, e.g. '$1', are checked to cover
+///   exactly the declared parameters, with no index skipped.
+/// - The `schema` and `unchecked` attributes relate to the `check-rusqlite` compile-time
+///   validation, see the root doc of the `fnsql` crate.
 /// - The `named` attribute allows using named arguments, e.g. ':name' with `postgres` in additon to the default position-based arguments of '$1' '$2', etc.
-
 #[proc_macro]
 pub fn fnsql(input: TokenStream) -> TokenStream {
     let queries: Queries = parse_macro_input!(input);
-    let queries: Vec<_> = queries.list.iter().map(|x| x.expand()).collect();
 
-    quote! { #(#queries)* }.into()
+    expand_queries(&queries.list, queries.module.as_ref()).into()
+}
+
+/// Same as `fnsql!`, but with the queries loaded from a `.sql` file, given relative to the
+/// crate directory:
+///
+/// ```ignore
+/// fnsql::fnsql_file!("queries/pets.sql");
+/// ```
+///
+/// Each query in the file starts with a `-- name:` header holding what would precede the
+/// SQL in `fnsql!`, optionally followed by `-- attrs:` headers holding its attributes:
+///
+/// ```sql
+/// -- name: get_pet_id_data(name: Option<String>) -> [(i32, Option<Vec<u8>>)]
+/// -- attrs: rusqlite, test(with=[create_table_pet])
+/// SELECT id, data FROM pet WHERE pet.name = :name
+/// ```
+///
+/// The SQL of the query extends up to the next `-- name:` header. Other comments right
+/// after the headers are the doc comment of the query. `-- attrs:` headers before the first query act like
+/// `#![...]` in `fnsql!`. Errors about a query refer to its line in the file.
+#[proc_macro]
+pub fn fnsql_file(input: TokenStream) -> TokenStream {
+    let path: LitStr = parse_macro_input!(input);
+
+    let crate_dir = std::env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let crate_dir = std::path::Path::new(&crate_dir);
+    let queries = match sql_file::load(crate_dir, &path) {
+        Ok(queries) => queries,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    // Rebuild when the file changes
+    let full_path = crate_dir.join(path.value()).to_string_lossy().into_owned();
+    let expansion = expand_queries(&queries, None);
+
+    quote! {
+        const _: &str = include_str!(#full_path);
+        #expansion
+    }.into()
+}
+
+fn expand_queries(queries: &[Query], module: Option<&Module>) -> Tokens {
+    #[allow(unused_mut)]
+    let mut errors: Vec<syn::Error> = vec![];
+    #[allow(unused_mut)]
+    let mut tracked = quote! {};
+
+    #[cfg(feature = "check-rusqlite")]
+    errors.extend(sqlite_check::check(queries));
+
+    #[cfg(feature = "check-postgres")]
+    {
+        let (pg_errors, pg_tracked) = postgres_check::check(queries);
+        errors.extend(pg_errors);
+        tracked = pg_tracked;
+    }
+
+    let errors: Vec<_> = errors.iter().map(|x| x.to_compile_error()).collect();
+    let expansion = match module {
+        None => {
+            let queries = queries.iter().map(|x| x.expand());
+            quote! { #(#queries)* }
+        }
+        Some(module) => module.expand(queries),
+    };
+
+    quote! { #(#errors)* #tracked #expansion }
+}
+
+impl Module {
+    /// Expands the queries inside the module, with the methods of all of them in a
+    /// single `Queries` extension trait. When backends are mixed, there is a trait per
+    /// backend instead, `RusqliteQueries` and `PostgresQueries`.
+    fn expand(&self, queries: &[Query]) -> Tokens {
+        let mut kinds = vec![];
+        for query in queries {
+            if !kinds.contains(&query.kind) {
+                kinds.push(query.kind);
+            }
+        }
+
+        let parts: Vec<_> = queries.iter().map(|x| (x.kind, x.expand_parts())).collect();
+        let traits = kinds.iter().map(|kind| {
+            let trait_name = match (kinds.len(), kind) {
+                (1, _) => "Queries",
+                (_, Kind::Rusqlite) => "RusqliteQueries",
+                (_, Kind::PostgreSQL) => "PostgresQueries",
+            };
+            let trait_name = Ident::new(trait_name, self.name.span());
+            let parts = parts.iter().filter(|(x, _)| x == kind);
+            let decls = parts.clone().map(|(_, x)| &x.decls);
+            let impls = parts.map(|(_, x)| &x.impls);
+
+            kind.extension_trait(&trait_name, quote! {}, quote! {},
+                quote! { #(#decls)* }, quote! { #(#impls)* })
+        });
+        let items = parts.iter().map(|(_, x)| &x.items);
+
+        let Module { attrs, vis, name } = self;
+        quote! {
+            #(#attrs)*
+            #vis mod #name {
+                #[allow(unused_imports)]
+                use super::*;
+
+                #(#traits)*
+                #(#items)*
+            }
+        }
+    }
+}
+
+/// Derives `fnsql::FromRow` for a struct, so that it can be used as a query output,
+/// e.g. `-> [Pet]`.
+///
+/// Fields of a struct with named fields are fetched by column name, and fields of a
+/// tuple struct are fetched by column index. Implementations are generated for the
+/// backends given by the `fnsql` attribute, i.e. `#[fnsql(rusqlite)]`,
+/// `#[fnsql(postgres)]` or `#[fnsql(rusqlite, postgres)]`, and fail with the error of
+/// the driver.
+#[proc_macro_derive(FromRow, attributes(fnsql))]
+pub fn from_row(input: TokenStream) -> TokenStream {
+    let input: syn::DeriveInput = parse_macro_input!(input);
+
+    match from_row::derive(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
 }
@@ -0,0 +1,191 @@
+//! Compile-time validation of `postgres` queries against the metadata that
+//! `fnsql-prepare` recorded under `.fnsql/`.
+//!
+//! A checked query without a recorded entry is an error, like a stale entry
+//! would be. The declared parameter and output types are compared to the
+//! recorded PostgreSQL types, where Rust types that are not known here are
+//! accepted as-is.
+
+use std::path::{Path, PathBuf};
+
+use proc_macro2::{Span, TokenStream as Tokens};
+use quote::quote;
+use serde_json::Value;
+
+use crate::cache;
+use crate::query::{CopyDirection, Kind, Query};
+
+/// Returns the PostgreSQL type names that a Rust type converts to and from,
+/// or `None` if the type is not known.
+fn pg_types(ttype: &syn::Type) -> Option<Vec<String>> {
+    match ttype {
+        syn::Type::Reference(reference) => pg_types(&reference.elem),
+        syn::Type::Group(group) => pg_types(&group.elem),
+        syn::Type::Paren(paren) => pg_types(&paren.elem),
+        syn::Type::Slice(slice) => array_types(&slice.elem),
+        syn::Type::Path(path) => {
+            let segment = path.path.segments.last()?;
+            let ident = segment.ident.to_string();
+            let names: &[&str] = match ident.as_str() {
+                "Option" | "Box" => return pg_types(generic_arg(segment)?),
+                "Vec" => return array_types(generic_arg(segment)?),
+                "bool" => &["bool"],
+                "i8" => &["char"],
+                "i16" => &["int2"],
+                "i32" => &["int4"],
+                "i64" => &["int8"],
+                "u32" => &["oid"],
+                "f32" => &["float4"],
+                "f64" => &["float8"],
+                "String" | "str" => &["text", "varchar", "bpchar", "name", "citext", "unknown"],
+                "Value" if is_serde_json(&path.path) => &["json", "jsonb"],
+                _ => return None,
+            };
+            Some(names.iter().map(|name| name.to_string()).collect())
+        }
+        _ => None,
+    }
+}
+
+fn is_serde_json(path: &syn::Path) -> bool {
+    path.segments.len() == 2 && path.segments[0].ident == "serde_json"
+}
+
+/// Types for a `Vec<T>` or `[T]`, which are either `bytea` or an array of `T`.
+fn array_types(elem: &syn::Type) -> Option<Vec<String>> {
+    if let syn::Type::Path(path) = elem {
+        if path.path.is_ident("u8") {
+            return Some(vec!["bytea".to_owned()]);
+        }
+    }
+
+    let names = pg_types(elem)?;
+    Some(names.iter().map(|name| format!("_{}", name)).collect())
+}
+
+fn generic_arg(segment: &syn::PathSegment) -> Option<&syn::Type> {
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.first()? {
+            syn::GenericArgument::Type(ttype) => Some(ttype),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn check_type(errors: &mut Vec<syn::Error>, query: &Query, ttype: &syn::Type,
+    recorded: &Value, what: std::fmt::Arguments)
+{
+    let recorded = match recorded.as_str() {
+        Some(recorded) => recorded,
+        None => return,
+    };
+
+    if let Some(names) = pg_types(ttype) {
+        if !names.iter().any(|name| name == recorded) {
+            errors.push(query.error(ttype, format!(
+                "{} has PostgreSQL type `{}`, which is incompatible with `{}`",
+                what, recorded, quote!(#ttype))));
+        }
+    }
+}
+
+fn check_query(query: &Query, path: &Path, json: &Value) -> Vec<syn::Error> {
+    let mut errors = vec![];
+    let empty = vec![];
+    let params = json["params"].as_array().unwrap_or(&empty);
+    let columns = json["columns"].as_array().unwrap_or(&empty);
+
+    let binds = query.binds();
+    if query.copy == Some(CopyDirection::In) {
+        // The recorded columns are those of the `SELECT` standing for the `COPY`,
+        // which the parameters bind.
+        if columns.len() != binds.len() {
+            errors.push(query.error(&query.query, format!(
+                "the `COPY` has {} columns according to {}, but the parameters bind {}",
+                columns.len(), path.display(), binds.len())));
+        } else {
+            for (bind, column) in binds.iter().zip(columns) {
+                if bind.field.is_none() {
+                    check_type(&mut errors, query, &bind.param.ttype, &column["type"],
+                        format_args!("column `{}`", column["name"].as_str().unwrap_or("?")));
+                }
+            }
+        }
+        return errors;
+    }
+
+    if params.len() != binds.len() {
+        errors.push(query.error(&query.query, format!(
+            "the query takes {} parameters according to {}, but {} are declared",
+            params.len(), path.display(), binds.len())));
+    } else {
+        for (bind, recorded) in binds.iter().zip(params) {
+            // The types of the fields of struct parameters are not known here.
+            if bind.field.is_none() {
+                check_type(&mut errors, query, &bind.param.ttype, recorded,
+                    format_args!("{}", bind));
+            }
+        }
+    }
+
+    if query.from_row.is_some() || query.outputs.is_empty() {
+        return errors;
+    }
+
+    if columns.len() != query.outputs.len() {
+        errors.push(query.error(&query.query, format!(
+            "the query returns {} columns, but {} output columns are declared",
+            columns.len(), query.outputs.len())));
+    } else {
+        for (output, column) in query.outputs.iter().zip(columns) {
+            check_type(&mut errors, query, &output.ttype, &column["type"],
+                format_args!("column `{}`", column["name"].as_str().unwrap_or("?")));
+        }
+    }
+
+    errors
+}
+
+/// Checks the `postgres` queries that have a recorded entry, returning the
+/// errors along with `include_bytes!` items so that the crate is rebuilt when
+/// the entries change.
+pub(crate) fn check(queries: &[Query]) -> (Vec<syn::Error>, Tokens) {
+    let crate_dir = match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => return (vec![], quote! {}),
+    };
+
+    let mut errors = vec![];
+    let mut tracked = vec![];
+
+    for query in queries {
+        if !matches!(query.kind, Kind::PostgreSQL) || query.unchecked || query.script
+            || query.channel.is_some()
+        {
+            continue;
+        }
+
+        let path = cache::path(&crate_dir, &query.described_sql());
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                errors.push(query.error(&query.query, format!(
+                    "unable to read {}: {}; run `fnsql-prepare` to record the query, \
+                     or mark it as `unchecked`", path.display(), err)));
+                continue;
+            }
+        };
+
+        match serde_json::from_str::<Value>(&contents) {
+            Ok(json) => errors.extend(check_query(query, &path, &json)),
+            Err(err) => errors.push(syn::Error::new(Span::call_site(),
+                format!("unable to parse {}: {}", path.display(), err))),
+        }
+
+        let path = path.to_string_lossy().into_owned();
+        tracked.push(quote! { const _: &[u8] = include_bytes!(#path); });
+    }
+
+    (errors, quote! { #(#tracked)* })
+}
@@ -0,0 +1,1390 @@
+//! Parsing of `fnsql!` input, and the checks that only depend on it.
+//!
+//! This module is shared with the `fnsql-prepare` binary, so it does not
+//! generate any code.
+
+use std::fmt::Display;
+use std::ops::{Range, RangeInclusive};
+
+use proc_macro2::Span;
+use quote::{quote, ToTokens};
+use syn::{
+    braced, bracketed, parenthesized,
+    parse::{Parse, ParseStream, Parser},
+    punctuated::Punctuated,
+    token, Ident, Token,
+};
+
+use crate::sql;
+
+pub(crate) struct Queries {
+    pub(crate) module: Option<Module>,
+    pub(crate) list: Vec<Query>,
+}
+
+/// A `mod <name> { ... }` wrapper around the queries, for generating a single
+/// extension trait in a module of their own.
+pub(crate) struct Module {
+    pub(crate) attrs: Vec<syn::Attribute>,
+    pub(crate) vis: syn::Visibility,
+    pub(crate) name: Ident,
+}
+
+impl Queries {
+    fn parse_list(input: ParseStream) -> syn::Result<Vec<Query>> {
+        let defaults = if input.peek(Token![#]) && input.peek2(Token![!]) {
+            input.parse()?
+        } else {
+            Defaults::default()
+        };
+
+        let mut list = vec![];
+        while !input.is_empty() {
+            list.extend(Query::parse_with_defaults(input, &defaults)?)
+        }
+
+        Ok(list)
+    }
+}
+
+impl Parse for Queries {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let is_module = {
+            let fork = input.fork();
+            fork.call(syn::Attribute::parse_outer).is_ok()
+                && fork.parse::<syn::Visibility>().is_ok()
+                && fork.peek(Token![mod])
+        };
+
+        if !is_module {
+            let list = Self::parse_list(input)?;
+            return Ok(Queries { module: None, list });
+        }
+
+        let attrs = input.call(syn::Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let _: Token![mod] = input.parse()?;
+        let name = input.parse()?;
+        let content;
+        let _ = braced!(content in input);
+        let list = Self::parse_list(&content)?;
+
+        if !input.is_empty() {
+            return Err(input.error("expected the end of the input after the module"));
+        }
+
+        Ok(Queries { module: Some(Module { attrs, vis, name }), list })
+    }
+}
+
+/// Attributes given to all queries of a block by a leading `#![...]`.
+#[derive(Default)]
+pub(crate) struct Defaults {
+    kinds: Vec<Kind>,
+    test: Option<Vec<Ident>>,
+    named: bool,
+}
+
+impl Parse for Defaults {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut defaults = Defaults::default();
+
+        let _: Token![#] = input.parse()?;
+        let _: Token![!] = input.parse()?;
+        let content;
+        let _ = bracketed!(content in input);
+
+        while !content.is_empty() {
+            let span = content.span();
+            match content.parse()? {
+                Attr::Kind(kind) => {
+                    if !defaults.kinds.contains(&kind) {
+                        defaults.kinds.push(kind);
+                    }
+                }
+                Attr::Test(test_attrs) => {
+                    let test = defaults.test.get_or_insert_with(Vec::new);
+                    for TestAttr::With(v) in test_attrs {
+                        test.extend(v);
+                    }
+                }
+                Attr::Named => {
+                    defaults.named = true;
+                }
+                _ => {
+                    return Err(syn::Error::new(span,
+                        "only backend, `named` and `test` attributes can be block-level defaults"));
+                }
+            }
+
+            if !content.is_empty() {
+                let _: Token![,] = content.parse()?;
+            }
+        }
+
+        Ok(defaults)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Kind {
+    Rusqlite,
+    PostgreSQL,
+}
+
+impl Kind {
+    pub(crate) const NAMES: &'static [&'static str] = &["rusqlite", "postgres"];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Kind::Rusqlite => "rusqlite",
+            Kind::PostgreSQL => "postgres",
+        }
+    }
+}
+
+/// A query given several backends, e.g. `#[rusqlite, postgres]`, is parsed into
+/// an instance per backend. The first one also emits what the instances share,
+/// such as the row struct.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Instance {
+    Single,
+    First,
+    Other,
+}
+
+#[derive(Clone)]
+pub(crate) struct Query {
+    pub(crate) name: Ident,
+    pub(crate) params: Vec<Param>,
+    pub(crate) outputs: Vec<Output>,
+    pub(crate) shape: Shape,
+    pub(crate) from_row: Option<syn::Type>,
+    pub(crate) query: syn::LitStr,
+    pub(crate) kind: Kind,
+    pub(crate) instance: Instance,
+    /// Whether the SQL is specific to the backend, being one of the variants
+    /// of a `{ rusqlite: "...", postgres: "..." }` body.
+    pub(crate) variant: bool,
+    pub(crate) test: Option<Vec<String>>,
+    pub(crate) named: bool,
+    pub(crate) serde: bool,
+    #[cfg_attr(not(feature = "check-rusqlite"), allow(dead_code))]
+    pub(crate) schema: bool,
+    #[cfg_attr(not(any(feature = "check-rusqlite", feature = "check-postgres")),
+        allow(dead_code))]
+    pub(crate) unchecked: bool,
+    /// An `UPDATE` whose assignments that use `Option` parameters are included
+    /// only when they are `Some`.
+    pub(crate) update: bool,
+    /// The number of rows that executing the query has to affect, e.g.
+    /// `expect_rows = 1` or `expect_rows = 0..=1`.
+    pub(crate) expect_rows: Option<RangeInclusive<u64>>,
+    /// The type of the row id returned by `execute_rowid_<name>`, for `rowid` or
+    /// `rowid = PetId`.
+    pub(crate) rowid: Option<syn::Type>,
+    /// Several statements, which are executed together by `execute_batch_<name>`.
+    pub(crate) script: bool,
+    /// Whether `insert_many_<name>` is generated, executing the query for each
+    /// item of an iterator.
+    pub(crate) many: bool,
+    /// A binary `COPY`, for which a typed writer or row iterator is generated
+    /// instead of the usual methods.
+    pub(crate) copy: Option<CopyDirection>,
+    /// A notification channel, whose name is held by `query`, and the format of
+    /// its payload.
+    pub(crate) channel: Option<PayloadFormat>,
+    /// Rust attributes to forward to the generated items, e.g. doc comments.
+    pub(crate) attrs: Vec<syn::Attribute>,
+    pub(crate) origin: Option<Origin>,
+}
+
+/// The number of rows that a query returns, according to the form of its output.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Shape {
+    /// Any number of rows, e.g. `-> [(i32, String)]`, or none for a query
+    /// without outputs.
+    Rows,
+    /// Exactly one row, e.g. `-> (i32, String)`.
+    One,
+    /// Zero or one row, e.g. `-> Option<(i32, String)>`.
+    Opt,
+    /// A single column of exactly one row, e.g. `-> i64`.
+    Scalar,
+}
+
+/// The direction of a `copy_in` or `copy_out` query.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum CopyDirection {
+    In,
+    Out,
+}
+
+impl CopyDirection {
+    pub(crate) fn attr_name(&self) -> &'static str {
+        match self {
+            CopyDirection::In => "copy_in",
+            CopyDirection::Out => "copy_out",
+        }
+    }
+}
+
+/// The format of the payload of a `channel`.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum PayloadFormat {
+    /// Plain text, converted with `Display` and `FromStr`.
+    Text,
+    /// JSON, converted with `serde_json`.
+    Json,
+}
+
+/// Where a query loaded by `fnsql_file!` comes from.
+#[derive(Clone)]
+pub(crate) struct Origin {
+    /// The span of the file path in the macro invocation.
+    pub(crate) span: Span,
+    /// The file and line of the query, e.g. `queries/pets.sql:12`.
+    pub(crate) location: String,
+}
+
+impl Query {
+    /// Parses a query into an instance per backend, where its own attributes
+    /// override the backends of the defaults, and extend their test
+    /// dependencies, or clear them with `test(with=[])`.
+    pub(crate) fn parse_with_defaults(input: ParseStream, defaults: &Defaults)
+        -> syn::Result<Vec<Self>>
+    {
+        let mut kinds = vec![];
+        let mut test = defaults.test.clone();
+        let mut named = defaults.named;
+        let mut serde = false;
+        let mut schema = false;
+        let mut unchecked = false;
+        let mut update = false;
+        let mut expect_rows = None;
+        let mut rowid = None;
+        let mut script = false;
+        let mut many = false;
+        let mut copy = None;
+        let mut channel = None;
+
+        let mut attrs = vec![];
+        let mut fnsql_attrs = vec![];
+        for attr in input.call(syn::Attribute::parse_outer)? {
+            if Attr::is_fnsql_attr(&attr) {
+                let path = &attr.path;
+                let tokens = &attr.tokens;
+                let parser = Punctuated::<Attr, Token![,]>::parse_terminated;
+                fnsql_attrs.extend(parser.parse2(quote! { #path #tokens })?);
+            } else {
+                attrs.push(attr);
+            }
+        }
+
+        for attr in fnsql_attrs {
+            match attr {
+                Attr::Kind(attr_kind) => {
+                    if !kinds.contains(&attr_kind) {
+                        kinds.push(attr_kind);
+                    }
+                }
+                Attr::Test(test_attrs) => {
+                    if test.is_none() {
+                        test = Some(vec![]);
+                    }
+                    for test_attr in test_attrs {
+                        match test_attr {
+                            TestAttr::With(v) if v.is_empty() => {
+                                // `test(with=[])` drops the default dependencies
+                                test.as_mut().unwrap().clear();
+                            }
+                            TestAttr::With(v) => {
+                                test.as_mut().unwrap().extend(v);
+                            }
+                        }
+                    }
+                }
+                Attr::Named => {
+                    named = true;
+                },
+                Attr::Serde => {
+                    serde = true;
+                },
+                Attr::Schema => {
+                    schema = true;
+                },
+                Attr::Unchecked => {
+                    unchecked = true;
+                },
+                Attr::Update => {
+                    update = true;
+                },
+                Attr::ExpectRows(range) => {
+                    expect_rows = Some(range);
+                },
+                Attr::Rowid(ttype) => {
+                    rowid = Some(*ttype);
+                },
+                Attr::Script => {
+                    script = true;
+                },
+                Attr::Many => {
+                    many = true;
+                },
+                Attr::Copy(span, direction) => {
+                    if copy.is_some() && copy != Some(direction) {
+                        return Err(syn::Error::new(span,
+                            "a query cannot be both `copy_in` and `copy_out`"));
+                    }
+                    copy = Some(direction);
+                },
+                Attr::Channel(format) => {
+                    channel = Some(format);
+                },
+            }
+        }
+
+        let name: Ident = input.parse()?;
+        if let Some(depend) = test.iter().flatten().find(|depend| **depend == name) {
+            return Err(syn::Error::new(depend.span(),
+                format!("query `{}` cannot depend on itself", name)));
+        }
+        let content;
+        let _ = parenthesized!(content in input);
+        let list: Punctuated<Param, Token![,]> = content.parse_terminated(Parse::parse)?;
+        let params: Vec<_> = list.into_iter().collect();
+        if let Some(param) = params.iter().filter(|param| param.list).nth(1) {
+            return Err(syn::Error::new(param.name.span(),
+                "a query can have only one list parameter"));
+        }
+        let names: Vec<_> = params.iter().flat_map(Param::binds).map(|bind| bind.name()).collect();
+        for (idx, name) in names.iter().enumerate() {
+            if names[..idx].contains(name) {
+                return Err(syn::Error::new(name.span(),
+                    format!("duplicate parameter `{}`", name)));
+            }
+        }
+
+        let mut from_row = None;
+        let mut shape = Shape::Rows;
+        let outputs = if input.peek(Token![->]) {
+            let _: Token![->] = input.parse()?;
+
+            let is_opt = {
+                let fork = input.fork();
+                matches!(fork.parse::<Ident>(), Ok(ident) if ident == "Option")
+                    && fork.parse::<Token![<]>().is_ok()
+                    && fork.peek(token::Paren)
+            };
+
+            if input.peek(token::Bracket) {
+                let content;
+                let _ = bracketed!(content in input);
+                if !content.peek(token::Paren) {
+                    from_row = Some(content.parse()?);
+                    vec![]
+                } else {
+                    Output::parse_list(&content)?
+                }
+            } else if input.peek(token::Paren) {
+                shape = Shape::One;
+                Output::parse_list(input)?
+            } else if is_opt {
+                shape = Shape::Opt;
+                let _: Ident = input.parse()?;
+                let _: Token![<] = input.parse()?;
+                let outputs = Output::parse_list(input)?;
+                let _: Token![>] = input.parse()?;
+                outputs
+            } else {
+                shape = Shape::Scalar;
+                vec![Output { name: None, ttype: input.parse()? }]
+            }
+        } else {
+            vec![]
+        };
+
+        if script && (!params.is_empty() || !outputs.is_empty() || from_row.is_some()) {
+            return Err(syn::Error::new(name.span(),
+                "a `script` query cannot have parameters or outputs"));
+        }
+
+        match copy {
+            Some(CopyDirection::In) if params.is_empty() || !outputs.is_empty() || from_row.is_some() => {
+                return Err(syn::Error::new(name.span(),
+                    "a `copy_in` query needs parameters for the copied columns, and cannot have outputs"));
+            }
+            Some(CopyDirection::Out) if !params.is_empty() || outputs.is_empty() || shape != Shape::Rows => {
+                return Err(syn::Error::new(name.span(),
+                    "a `copy_out` query needs output columns for the copied columns, e.g. `-> [(i32, String)]`, and cannot have parameters"));
+            }
+            Some(copy) if update || expect_rows.is_some() || script || many => {
+                return Err(syn::Error::new(name.span(), format!(
+                    "the `{}` attribute cannot be combined with `update`, `expect_rows`, `script` or `many`",
+                    copy.attr_name())));
+            }
+            _ => {}
+        }
+
+        if channel.is_some() {
+            let payload = match params.as_slice() {
+                [param] => !param.list && param.fields.is_none(),
+                _ => false,
+            };
+            if !payload || !outputs.is_empty() || from_row.is_some() {
+                return Err(syn::Error::new(name.span(),
+                    "a `channel` needs a single payload parameter, e.g. `pet_changed(payload: PetEvent)`, and cannot have outputs"));
+            }
+            if update || expect_rows.is_some() || script || many || copy.is_some() {
+                return Err(syn::Error::new(name.span(),
+                    "the `channel` attribute cannot be combined with `update`, `expect_rows`, `script`, `many` or `copy_in`/`copy_out`"));
+            }
+        }
+
+        if shape != Shape::Rows && expect_rows.is_some() {
+            return Err(syn::Error::new(name.span(),
+                "`expect_rows` applies to the `execute_` methods, which a query returning a single row does not have"));
+        }
+
+        if shape != Shape::Rows && outputs.is_empty() {
+            return Err(syn::Error::new(name.span(),
+                "a query returning a single row needs at least one output column"));
+        }
+
+        if serde && !outputs.iter().any(|x| x.name.is_some()) {
+            return Err(syn::Error::new(name.span(),
+                "the `serde` attribute requires named output columns"));
+        }
+
+        let (common, variants) = if channel.is_some() && !input.peek(token::Brace) {
+            // A channel is named after the query, unless a body gives its name.
+            if input.peek(Token![;]) {
+                let _: Token![;] = input.parse()?;
+            }
+            (Some(syn::LitStr::new(&name.to_string(), name.span())), vec![])
+        } else {
+            let content;
+            let _ = braced!(content in input);
+            if content.peek(syn::LitStr) {
+                (Some(content.parse::<syn::LitStr>()?), vec![])
+            } else {
+                let list: Punctuated<Variant, Token![,]> = content.parse_terminated(Parse::parse)?;
+                if list.is_empty() {
+                    return Err(content.error("expected the SQL of the query"));
+                }
+                (None, list.into_iter().collect())
+            }
+        };
+
+        let mut errors = vec![];
+        for (idx, variant) in variants.iter().enumerate() {
+            if variants[..idx].iter().any(|other| other.kind == variant.kind) {
+                errors.push(syn::Error::new(variant.span,
+                    format!("duplicate SQL for `{}`", variant.kind.name())));
+            } else if !kinds.is_empty() && !kinds.contains(&variant.kind) {
+                errors.push(syn::Error::new(variant.span, format!(
+                    "SQL for `{}`, which is not a backend of `{}`", variant.kind.name(), name)));
+            }
+        }
+        if kinds.is_empty() && !variants.is_empty() {
+            // The backends of a query with per-backend SQL are those of its
+            // variants, unless given explicitly.
+            for variant in &variants {
+                if !kinds.contains(&variant.kind) {
+                    kinds.push(variant.kind);
+                }
+            }
+        }
+        if kinds.is_empty() {
+            kinds = defaults.kinds.clone();
+        }
+        if kinds.is_empty() {
+            errors.push(syn::Error::new(name.span(), format!(
+                "missing SQL backend attribute for `{}`. Supported: {}",
+                name, Kind::NAMES.join(", "))));
+        }
+        if rowid.is_some() && kinds.contains(&Kind::PostgreSQL) {
+            errors.push(syn::Error::new(name.span(),
+                "the `rowid` attribute is only supported by `rusqlite`, use `RETURNING` for `postgres`"));
+        }
+        if let Some(copy) = copy {
+            if kinds.contains(&Kind::Rusqlite) {
+                errors.push(syn::Error::new(name.span(), format!(
+                    "the `{}` attribute is only supported by `postgres`", copy.attr_name())));
+            }
+        }
+        if channel.is_some() && kinds.contains(&Kind::Rusqlite) {
+            errors.push(syn::Error::new(name.span(),
+                "the `channel` attribute is only supported by `postgres`"));
+        }
+        if !variants.is_empty() {
+            for kind in &kinds {
+                if !variants.iter().any(|variant| variant.kind == *kind) {
+                    errors.push(syn::Error::new(name.span(), format!(
+                        "missing SQL for `{}` in `{}`", kind.name(), name)));
+                }
+            }
+        }
+
+        let mut errors = errors.into_iter();
+        if let Some(mut error) = errors.next() {
+            error.extend(errors);
+            return Err(error);
+        }
+
+        let sql = |kind: Kind| match &common {
+            Some(query) => query.clone(),
+            None => variants.iter().find(|variant| variant.kind == kind).unwrap().query.clone(),
+        };
+
+        let query = Query {
+            name,
+            params,
+            outputs,
+            shape,
+            query: sql(kinds[0]),
+            kind: kinds[0],
+            instance: Instance::Single,
+            variant: common.is_none(),
+            test: test.map(|test| test.iter().map(ToString::to_string).collect()),
+            named,
+            serde,
+            schema,
+            unchecked,
+            update,
+            expect_rows,
+            rowid,
+            script,
+            many,
+            copy,
+            channel,
+            from_row,
+            attrs,
+            origin: None,
+        };
+
+        if kinds.len() == 1 {
+            return Ok(vec![query]);
+        }
+
+        // Placeholders are written once as `:name`, and are translated for
+        // the backends that need it.
+        Ok(kinds.iter().enumerate().map(|(idx, kind)| Query {
+            query: sql(*kind),
+            kind: *kind,
+            instance: if idx == 0 { Instance::First } else { Instance::Other },
+            named: true,
+            ..query.clone()
+        }).collect())
+    }
+
+    /// An error about a part of the query. For queries loaded by
+    /// `fnsql_file!`, the error is placed on the file path instead, and refers
+    /// to the line of the query.
+    pub(crate) fn error<T: ToTokens>(&self, tokens: T, message: impl Display) -> syn::Error {
+        match &self.origin {
+            None => syn::Error::new_spanned(tokens, message),
+            Some(origin) => syn::Error::new(origin.span,
+                format!("{}: {}", origin.location, message)),
+        }
+    }
+
+    /// Verify that the placeholders in the SQL and the declared parameters
+    /// match each other.
+    pub(crate) fn check_placeholders(&self) -> syn::Result<()> {
+        let uses_named = match self.kind {
+            Kind::Rusqlite => true,
+            Kind::PostgreSQL => self.named,
+        };
+
+        // The parameters of a `COPY` are its columns, rather than placeholders.
+        let mut errors = if self.copy.is_some() {
+            self.check_copy()
+        } else if self.channel.is_some() {
+            self.check_channel()
+        } else if uses_named {
+            self.check_named_placeholders()
+        } else {
+            self.check_positional_placeholders()
+        };
+        errors.extend(self.check_list_placeholders());
+        errors.extend(self.check_fragments());
+        errors.extend(self.check_update());
+        errors.extend(self.check_rowid());
+        errors.extend(self.check_many());
+
+        let mut errors = errors.into_iter();
+        match errors.next() {
+            None => Ok(()),
+            Some(mut error) => {
+                error.extend(errors);
+                Err(error)
+            }
+        }
+    }
+
+    fn check_named_placeholders(&self) -> Vec<syn::Error> {
+        let sql = self.query.value();
+        let placeholders = sql::named_placeholders(&sql);
+        let mut errors = vec![];
+
+        if let Some(index) = sql::positional_placeholders(&sql).first() {
+            errors.push(self.error(&self.query, format!(
+                "positional placeholder `${}` in a query using named placeholders", index)));
+        }
+
+        for placeholder in &placeholders {
+            if !self.binds().iter().any(|bind| bind.name() == placeholder) {
+                errors.push(self.error(&self.query, format!(
+                    "placeholder `:{}` has no matching parameter", placeholder)));
+            }
+        }
+
+        for bind in self.binds() {
+            if !placeholders.iter().any(|placeholder| bind.name() == placeholder) {
+                errors.push(self.error(bind.name(), format!(
+                    "{} is not used by the query", bind)));
+            }
+        }
+
+        errors
+    }
+
+    fn check_positional_placeholders(&self) -> Vec<syn::Error> {
+        let sql = self.query.value();
+        let mut indices = sql::positional_placeholders(&sql);
+        let mut errors = vec![];
+
+        if let Some(name) = sql::named_placeholders(&sql).first() {
+            errors.push(self.error(&self.query, format!(
+                "placeholder `:{}` requires the `named` attribute", name)));
+        }
+
+        indices.sort_unstable();
+        indices.dedup();
+
+        let binds = self.binds();
+        let highest = indices.last().copied().unwrap_or(0);
+        if highest > binds.len() {
+            errors.push(self.error(&self.query, format!(
+                "placeholder `${}` exceeds the {} declared parameters", highest,
+                binds.len())));
+        }
+
+        for (idx, bind) in binds.iter().enumerate() {
+            if indices.binary_search(&(idx + 1)).is_err() {
+                errors.push(self.error(bind.name(), format!(
+                    "{} is not used by the query, as `${}` is missing",
+                    bind, idx + 1)));
+            }
+        }
+
+        if indices.first() == Some(&0) {
+            errors.push(self.error(&self.query,
+                "placeholders are numbered from `$1`"));
+        }
+
+        errors
+    }
+
+    /// The values bound to the placeholders, in the order of their positional
+    /// placeholders: the parameters, with struct parameters expanded to their fields.
+    pub(crate) fn binds(&self) -> Vec<Bind<'_>> {
+        self.params.iter().flat_map(Param::binds).collect()
+    }
+
+    /// The value that a placeholder refers to, if any.
+    pub(crate) fn bind_of(&self, placeholder: &sql::Placeholder<'_>) -> Option<Bind<'_>> {
+        match placeholder {
+            sql::Placeholder::Named(name) => self.binds().into_iter().find(|bind| bind.name() == name),
+            sql::Placeholder::Positional(_) if self.named => None,
+            sql::Placeholder::Positional(index) => self.binds().get(index.wrapping_sub(1)).copied(),
+        }
+    }
+
+    /// The `Option` parameter that a placeholder refers to, if any.
+    pub(crate) fn option_param_of(&self, placeholder: &sql::Placeholder<'_>) -> Option<&Param> {
+        match self.bind_of(placeholder) {
+            Some(Bind { param, field: None }) if param.is_option() => Some(param),
+            _ => None,
+        }
+    }
+
+    /// The list parameter that a placeholder refers to, if any.
+    pub(crate) fn list_param_of(&self, placeholder: &sql::Placeholder<'_>) -> Option<&Param> {
+        self.bind_of(placeholder).map(|bind| bind.param).filter(|param| param.list)
+    }
+
+    /// The optional fragments of the SQL, e.g. `[AND name = :name]`. These are
+    /// the bracketed sections that use an `Option` parameter, and are included
+    /// only when it is `Some`.
+    pub(crate) fn fragments(&self) -> Vec<Range<usize>> {
+        let fragments = self.all_fragments();
+        fragments
+            .iter()
+            .filter(|range| !Self::is_nested(range, &fragments))
+            .cloned()
+            .collect()
+    }
+
+    /// Like `fragments`, including fragments nested in others, which are errors.
+    fn all_fragments(&self) -> Vec<Range<usize>> {
+        let sql = self.query.value();
+        let placeholders = sql::placeholders(&sql);
+
+        sql::bracketed(&sql)
+            .into_iter()
+            .filter(|range| {
+                placeholders.iter().any(|(placeholder_range, placeholder)| {
+                    range.contains(&placeholder_range.start)
+                        && self.option_param_of(placeholder).is_some()
+                })
+            })
+            .collect()
+    }
+
+    fn is_nested(range: &Range<usize>, fragments: &[Range<usize>]) -> bool {
+        fragments.iter().any(|other| {
+            other != range && other.start <= range.start && range.end <= other.end
+        })
+    }
+
+    fn check_fragments(&self) -> Vec<syn::Error> {
+        let fragments = self.all_fragments();
+        let mut errors = vec![];
+
+        for range in &fragments {
+            if Self::is_nested(range, &fragments) {
+                errors.push(self.error(&self.query, format!(
+                    "optional fragment `{}` cannot be nested in another one",
+                    &self.query.value()[range.clone()])));
+            }
+        }
+
+        errors
+    }
+
+    /// The assignments of the `SET` clause of an `update` query, along with the
+    /// `Option` parameters that each of them depends on.
+    pub(crate) fn assignments(&self) -> Vec<(Range<usize>, Vec<&Ident>)> {
+        let sql = self.query.value();
+        let assignments = match sql::set_clause(&sql) {
+            Some(assignments) if self.update => assignments,
+            _ => return vec![],
+        };
+
+        assignments
+            .into_iter()
+            .map(|range| {
+                let mut depends = vec![];
+                for (_, placeholder) in sql::placeholders(&sql[range.clone()]) {
+                    match self.option_param_of(&placeholder) {
+                        Some(param) if !depends.contains(&&param.name) => {
+                            depends.push(&param.name);
+                        }
+                        _ => {}
+                    }
+                }
+                (range, depends)
+            })
+            .collect()
+    }
+
+    fn check_update(&self) -> Vec<syn::Error> {
+        let sql = self.query.value();
+        let mut errors = vec![];
+
+        if self.update && sql::set_clause(&sql).is_none() {
+            errors.push(self.error(&self.query,
+                "the `update` attribute requires an `UPDATE ... SET ...` query"));
+        }
+
+        for (range, _) in self.assignments() {
+            if self.fragments().iter().any(|fragment| range.contains(&fragment.start)) {
+                errors.push(self.error(&self.query, format!(
+                    "the assignment `{}` is already optional, and cannot have optional fragments",
+                    &sql[range])));
+            }
+        }
+
+        errors
+    }
+
+    fn check_rowid(&self) -> Vec<syn::Error> {
+        let dynamic = !self.fragments().is_empty()
+            || self.update
+            || self.params.iter().any(|param| param.list);
+
+        if self.rowid.is_some() && dynamic {
+            vec![self.error(&self.name,
+                "the `rowid` attribute is not supported with list parameters, optional fragments or `update`")]
+        } else {
+            vec![]
+        }
+    }
+
+    fn check_many(&self) -> Vec<syn::Error> {
+        if !self.many {
+            return vec![];
+        }
+
+        // Lists are bound as arrays for `postgres`, so only `rusqlite` builds
+        // their SQL at runtime.
+        let dynamic = !self.fragments().is_empty()
+            || self.update
+            || (self.kind == Kind::Rusqlite && self.params.iter().any(|param| param.list));
+
+        let message = if self.params.is_empty() {
+            "the `many` attribute requires parameters"
+        } else if dynamic {
+            "the `many` attribute is not supported with optional fragments or `update`, or with list parameters for `rusqlite`"
+        } else if self.script || self.shape != Shape::Rows || self.is_returning() {
+            "the `many` attribute requires a query that returns no rows"
+        } else {
+            return vec![];
+        };
+
+        vec![self.error(&self.name, message)]
+    }
+
+    fn check_copy(&self) -> Vec<syn::Error> {
+        let copy = match self.copy {
+            Some(copy) => copy,
+            None => return vec![],
+        };
+        let example = match copy {
+            CopyDirection::In => "COPY pet (id, name) FROM STDIN BINARY",
+            CopyDirection::Out => "COPY pet (id, name) TO STDOUT BINARY",
+        };
+
+        let statement = match self.copy_statement() {
+            Some(statement) if statement.from_stdin == (copy == CopyDirection::In) => statement,
+            _ => {
+                let direction = match copy {
+                    CopyDirection::In => "FROM STDIN",
+                    CopyDirection::Out => "TO STDOUT",
+                };
+                return vec![self.error(&self.query, format!(
+                    "a `{}` query must be a `COPY ... {}` statement", copy.attr_name(), direction))];
+            }
+        };
+
+        let mut errors = vec![];
+        if !statement.binary {
+            errors.push(self.error(&self.query, format!(
+                "a `{}` query must use the binary format, e.g. `{}`", copy.attr_name(), example)));
+        }
+
+        match (copy, statement.columns) {
+            (CopyDirection::In, None) => {
+                errors.push(self.error(&self.query, format!(
+                    "a `copy_in` query needs a column list, e.g. `{}`", example)));
+            }
+            (CopyDirection::In, Some(columns)) if columns != self.binds().len() => {
+                errors.push(self.error(&self.query, format!(
+                    "the `COPY` has {} columns, but the parameters bind {}",
+                    columns, self.binds().len())));
+            }
+            (CopyDirection::Out, Some(columns)) if columns != self.outputs.len() => {
+                errors.push(self.error(&self.query, format!(
+                    "the `COPY` has {} columns, but {} output columns are declared",
+                    columns, self.outputs.len())));
+            }
+            _ => {}
+        }
+
+        errors
+    }
+
+    fn check_channel(&self) -> Vec<syn::Error> {
+        if self.query.value().is_empty() {
+            return vec![self.error(&self.query, "the name of a channel cannot be empty")];
+        }
+
+        vec![]
+    }
+
+    /// The `COPY` statement of a `copy_in` or `copy_out` query.
+    pub(crate) fn copy_statement(&self) -> Option<sql::CopyStatement> {
+        self.copy?;
+        sql::copy_statement(&self.query.value())
+    }
+
+    /// The SQL whose parameter and column types `fnsql-prepare` records. A `COPY`
+    /// cannot be described, so for it this is a `SELECT` of the copied columns.
+    #[cfg_attr(not(feature = "check-postgres"), allow(dead_code))]
+    pub(crate) fn described_sql(&self) -> String {
+        match self.copy_statement() {
+            Some(statement) => statement.select,
+            None => self.postgres_sql(),
+        }
+    }
+
+    /// Whether executing the query returns rows, as it has output columns and a
+    /// `RETURNING` clause.
+    pub(crate) fn is_returning(&self) -> bool {
+        self.shape == Shape::Rows
+            && (!self.outputs.is_empty() || self.from_row.is_some())
+            && sql::has_returning(&self.query.value())
+    }
+
+    /// The SQL with all of its optional fragments included, which is what gets
+    /// checked at compile time.
+    pub(crate) fn full_sql(&self) -> String {
+        let sql = self.query.value();
+        let mut result = String::new();
+        let mut last = 0;
+
+        for range in self.fragments() {
+            result.push_str(&sql[last..range.start]);
+            result.push_str(&sql[range.start + 1..range.end - 1]);
+            last = range.end;
+        }
+
+        result.push_str(&sql[last..]);
+        result
+    }
+
+    fn check_list_placeholders(&self) -> Vec<syn::Error> {
+        let sql = self.query.value();
+        let mut errors = vec![];
+
+        for (range, placeholder) in sql::placeholders(&sql) {
+            if let Some(param) = self.list_param_of(&placeholder) {
+                if sql::in_list(&sql, range.clone()).is_none() {
+                    errors.push(self.error(&self.query, format!(
+                        "list parameter `{}` must be used as `IN ({})`",
+                        param.name, &sql[range])));
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// The SQL as passed to PostgreSQL, where named placeholders are
+    /// translated to positional ones, and lists are bound as arrays.
+    pub(crate) fn postgres_sql(&self) -> String {
+        let sql = self.full_sql();
+        let sql = if self.params.iter().any(|param| param.list) {
+            sql::replace_in_lists(&sql, |placeholder| self.list_param_of(placeholder).is_some())
+        } else {
+            sql
+        };
+
+        if self.named {
+            sql::replace_named(&sql, |name| {
+                self.binds()
+                    .iter()
+                    .position(|bind| bind.name() == name)
+                    .map(|idx| format!("${}", idx + 1))
+            })
+        } else {
+            sql
+        }
+    }
+}
+
+/// The SQL of one backend in a `{ rusqlite: "...", postgres: "..." }` body.
+struct Variant {
+    kind: Kind,
+    span: Span,
+    query: syn::LitStr,
+}
+
+impl Parse for Variant {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+        let kind = match ident.to_string().as_str() {
+            "rusqlite" => Kind::Rusqlite,
+            "postgres" => Kind::PostgreSQL,
+            _ => return Err(syn::Error::new(ident.span(), format!(
+                "unknown SQL backend `{}`. Supported: {}", ident, Kind::NAMES.join(", ")))),
+        };
+        let _: Token![:] = input.parse()?;
+        let query = input.parse()?;
+
+        Ok(Self { kind, span: ident.span(), query })
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Output {
+    pub(crate) name: Option<Ident>,
+    pub(crate) ttype: syn::Type,
+}
+
+impl Output {
+    /// Parses a parenthesized list of output columns, e.g. `(id: i32, name: String)`.
+    fn parse_list(input: ParseStream) -> syn::Result<Vec<Self>> {
+        let content;
+        let _ = parenthesized!(content in input);
+        let list: Punctuated<Output, Token![,]> = content.parse_terminated(Parse::parse)?;
+        let outputs: Vec<_> = list.into_iter().collect();
+        let named_count = outputs.iter().filter(|x| x.name.is_some()).count();
+        if named_count != 0 && named_count != outputs.len() {
+            return Err(syn::Error::new(content.span(),
+                "either all output columns are named or none of them are"));
+        }
+        Ok(outputs)
+    }
+}
+
+impl Parse for Output {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = if input.peek(Ident) && input.peek2(Token![:]) && !input.peek2(Token![::]) {
+            let name = input.parse()?;
+            let _: Token![:] = input.parse()?;
+            Some(name)
+        } else {
+            None
+        };
+        let ttype = input.parse()?;
+
+        Ok(Self { name, ttype })
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Param {
+    pub(crate) name: Ident,
+    pub(crate) ttype: syn::Type,
+    /// A slice declared with `as list`, e.g. `ids: [i64] as list`, which is
+    /// used as `IN (:ids)`.
+    pub(crate) list: bool,
+    /// The fields of a struct parameter, e.g. `pet: &Pet { id, name }`, which
+    /// are bound to the placeholders named after them.
+    pub(crate) fields: Option<Vec<Ident>>,
+}
+
+impl Param {
+    const MODIFIERS: &'static [&'static str] = &["list"];
+
+    pub(crate) fn is_option(&self) -> bool {
+        match &self.ttype {
+            syn::Type::Path(path) => matches!(path.path.segments.last(),
+                Some(segment) if segment.ident == "Option"),
+            _ => false,
+        }
+    }
+
+    fn binds(&self) -> Vec<Bind<'_>> {
+        match &self.fields {
+            None => vec![Bind { param: self, field: None }],
+            Some(fields) => fields.iter().map(|field| Bind { param: self, field: Some(field) }).collect(),
+        }
+    }
+}
+
+impl Parse for Param {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        let _: Token![:] = input.parse()?;
+        let mut ttype = input.parse()?;
+
+        let mut fields = None;
+        if input.peek(token::Brace) {
+            // Parameters are taken by reference anyway, so `&Pet { .. }` is the
+            // same as `Pet { .. }`.
+            if let syn::Type::Reference(reference) = &ttype {
+                ttype = (*reference.elem).clone();
+            }
+            let content;
+            let _ = braced!(content in input);
+            let list: Punctuated<Ident, Token![,]> = content.parse_terminated(Parse::parse)?;
+            if list.is_empty() {
+                return Err(syn::Error::new_spanned(&ttype,
+                    "a struct parameter needs at least one field, e.g. `pet: &Pet { id }`"));
+            }
+            fields = Some(list.into_iter().collect());
+        }
+
+        let mut list = false;
+        if input.peek(Token![as]) {
+            let _: Token![as] = input.parse()?;
+            let modifier: Ident = input.parse()?;
+            if modifier != "list" {
+                return Err(syn::Error::new(modifier.span(), format!(
+                    "unknown parameter modifier `{}`. Supported: {}",
+                    modifier, Param::MODIFIERS.join(", "))));
+            }
+            if fields.is_some() || !matches!(ttype, syn::Type::Slice(_)) {
+                return Err(syn::Error::new_spanned(&ttype,
+                    "a list parameter is declared as a slice, e.g. `[i64] as list`"));
+            }
+            list = true;
+        }
+
+        Ok(Self { name, ttype, list, fields })
+    }
+}
+
+/// A value bound to a placeholder, which is either a parameter, or a field of
+/// a struct parameter.
+#[derive(Clone, Copy)]
+pub(crate) struct Bind<'a> {
+    pub(crate) param: &'a Param,
+    pub(crate) field: Option<&'a Ident>,
+}
+
+impl<'a> Bind<'a> {
+    /// The name of the placeholder.
+    pub(crate) fn name(&self) -> &'a Ident {
+        self.field.unwrap_or(&self.param.name)
+    }
+}
+
+impl Display for Bind<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.field {
+            None => write!(f, "parameter `{}`", self.param.name),
+            Some(field) => write!(f, "field `{}` of parameter `{}`", field, self.param.name),
+        }
+    }
+}
+
+enum Attr {
+    Kind(Kind),
+    Test(Vec<TestAttr>),
+    Named,
+    Serde,
+    Schema,
+    Unchecked,
+    Update,
+    ExpectRows(RangeInclusive<u64>),
+    Rowid(Box<syn::Type>),
+    Script,
+    Many,
+    Copy(Span, CopyDirection),
+    Channel(PayloadFormat),
+}
+
+impl Attr {
+    const NAMES: &'static [&'static str] = &[
+        "rusqlite", "postgres", "named", "serde", "schema", "unchecked", "update", "expect_rows",
+        "rowid", "script", "many", "copy_in", "copy_out", "channel", "test",
+    ];
+
+    /// Names that are Rust attributes as well, which are only taken as fnsql
+    /// attributes as a bare word, e.g. `#[serde]` but not `#[serde(rename = "x")]`.
+    const RUST_NAMES: &'static [&'static str] = &["serde"];
+
+    /// Whether an outer attribute is a list of fnsql attributes rather than a
+    /// Rust attribute. A list that does not start with a known name, such as
+    /// `#[rusqlit, test]`, or a single name that is close to a known one, such
+    /// as `#[rusqlit]`, is still taken so that it gets reported.
+    fn is_fnsql_attr(attr: &syn::Attribute) -> bool {
+        let ident = match attr.path.get_ident() {
+            Some(ident) => ident.to_string(),
+            None => return false,
+        };
+        let first = attr.tokens.clone().into_iter().next();
+        let list = matches!(&first,
+            Some(proc_macro2::TokenTree::Punct(punct)) if punct.as_char() == ',');
+        let known = Attr::NAMES.contains(&ident.as_str())
+            && (first.is_none() || list || !Attr::RUST_NAMES.contains(&ident.as_str()));
+        let misspelled = !Attr::NAMES.contains(&ident.as_str()) && Attr::NAMES.iter()
+            .any(|name| edit_distance(&ident, name) <= (name.len() / 3).max(1));
+        known || list || misspelled
+    }
+}
+
+/// The Levenshtein distance between two names.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+impl Parse for Attr {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+        if ident == "rusqlite" {
+            return Ok(Attr::Kind(Kind::Rusqlite));
+        }
+        if ident == "postgres" {
+            return Ok(Attr::Kind(Kind::PostgreSQL));
+        }
+        if ident == "named" {
+            return Ok(Attr::Named);
+        }
+        if ident == "serde" {
+            return Ok(Attr::Serde);
+        }
+        if ident == "schema" {
+            return Ok(Attr::Schema);
+        }
+        if ident == "unchecked" {
+            return Ok(Attr::Unchecked);
+        }
+        if ident == "update" {
+            return Ok(Attr::Update);
+        }
+        if ident == "many" {
+            return Ok(Attr::Many);
+        }
+        if ident == "script" {
+            return Ok(Attr::Script);
+        }
+        if ident == "channel" {
+            let format = if input.peek(Token![=]) {
+                let _: Token![=] = input.parse()?;
+                let format: Ident = input.parse()?;
+                if format == "text" {
+                    PayloadFormat::Text
+                } else if format == "json" {
+                    PayloadFormat::Json
+                } else {
+                    return Err(syn::Error::new(format.span(), format!(
+                        "unknown payload format `{}`. Supported: text, json", format)));
+                }
+            } else {
+                PayloadFormat::Text
+            };
+            return Ok(Attr::Channel(format));
+        }
+        if ident == "copy_in" {
+            return Ok(Attr::Copy(ident.span(), CopyDirection::In));
+        }
+        if ident == "copy_out" {
+            return Ok(Attr::Copy(ident.span(), CopyDirection::Out));
+        }
+        if ident == "rowid" {
+            let ttype = if input.peek(Token![=]) {
+                let _: Token![=] = input.parse()?;
+                input.parse()?
+            } else {
+                syn::parse_quote! { i64 }
+            };
+            return Ok(Attr::Rowid(Box::new(ttype)));
+        }
+        if ident == "expect_rows" {
+            let _: Token![=] = input.parse()?;
+            let start: syn::LitInt = input.parse()?;
+            let end = if input.peek(Token![..=]) {
+                let _: Token![..=] = input.parse()?;
+                input.parse()?
+            } else {
+                start.clone()
+            };
+            let range = start.base10_parse()?..=end.base10_parse()?;
+            if range.is_empty() {
+                return Err(syn::Error::new(end.span(), "the range of `expect_rows` is empty"));
+            }
+            return Ok(Attr::ExpectRows(range));
+        }
+        if ident == "test" {
+            let mut v = vec![];
+
+            if input.peek(token::Paren) {
+                let content;
+                let _ = parenthesized!(content in input);
+                let list: Punctuated<TestAttr, Token![,]> =
+                    content.parse_terminated(Parse::parse)?;
+                v = list.into_iter().collect();
+            };
+
+            return Ok(Attr::Test(v));
+        }
+        Err(syn::Error::new(ident.span(), format!(
+            "unknown attribute `{}`. Supported: {}", ident, Attr::NAMES.join(", "))))
+    }
+}
+
+enum TestAttr {
+    With(Vec<Ident>),
+}
+
+impl TestAttr {
+    const NAMES: &'static [&'static str] = &["with"];
+}
+
+impl Parse for TestAttr {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+        if ident == "with" {
+            let mut v = vec![];
+
+            let _: Token![=] = input.parse()?;
+            let content;
+            let _ = bracketed!(content in input);
+            let list: Punctuated<Ident, Token![,]> = content.parse_terminated(Parse::parse)?;
+            for item in list {
+                v.push(item);
+            }
+
+            return Ok(TestAttr::With(v));
+        }
+
+        Err(syn::Error::new(ident.span(), format!(
+            "unknown test attribute `{}`. Supported: {}", ident, TestAttr::NAMES.join(", "))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_fnsql_attr(attr: proc_macro2::TokenStream) -> bool {
+        let attrs = syn::Attribute::parse_outer.parse2(attr).unwrap();
+        Attr::is_fnsql_attr(&attrs[0])
+    }
+
+    #[test]
+    fn fnsql_attrs() {
+        assert!(is_fnsql_attr(quote! { #[rusqlite, test(with=[a])] }));
+        assert!(is_fnsql_attr(quote! { #[test] }));
+        assert!(is_fnsql_attr(quote! { #[serde] }));
+        assert!(is_fnsql_attr(quote! { #[serde, rusqlite] }));
+        assert!(is_fnsql_attr(quote! { #[expect_rows = 1] }));
+    }
+
+    #[test]
+    fn rust_attrs() {
+        assert!(!is_fnsql_attr(quote! { #[serde(rename = "pet")] }));
+        assert!(!is_fnsql_attr(quote! { #[deprecated(note = "x")] }));
+        assert!(!is_fnsql_attr(quote! { #[deny(warnings)] }));
+        assert!(!is_fnsql_attr(quote! { #[doc = "x"] }));
+        assert!(!is_fnsql_attr(quote! { #[rustfmt::skip] }));
+    }
+
+    #[test]
+    fn misspelled_attrs() {
+        assert!(is_fnsql_attr(quote! { #[rusqlit] }));
+        assert!(is_fnsql_attr(quote! { #[rusqlit, test] }));
+        assert!(is_fnsql_attr(quote! { #[expect_row = 1] }));
+        assert!(is_fnsql_attr(quote! { #[scirpt] }));
+    }
+
+    fn parse_error(input: proc_macro2::TokenStream) -> Option<String> {
+        syn::parse2::<Queries>(input).err().map(|err| err.to_string())
+    }
+
+    #[test]
+    fn self_dependency() {
+        let error = Some("query `create` cannot depend on itself".to_owned());
+        assert_eq!(parse_error(quote! {
+            #[rusqlite, test(with=[create])]
+            create() { "CREATE TABLE t (id INTEGER)" }
+        }), error);
+        assert_eq!(parse_error(quote! {
+            #![rusqlite, test(with=[create])]
+            create() { "CREATE TABLE t (id INTEGER)" }
+        }), error);
+        assert_eq!(parse_error(quote! {
+            #![rusqlite, test(with=[create])]
+            #[test(with=[])]
+            create() { "CREATE TABLE t (id INTEGER)" }
+            get() -> [(i32)] { "SELECT id FROM t" }
+        }), None);
+    }
+}
@@ -0,0 +1,706 @@
+//! A minimal SQL lexer, only as deep as needed for finding query placeholders
+//! and brackets outside of string literals (including `E'...'` escape
+//! strings), quoted identifiers, dollar-quoted bodies, comments, `::` casts and
+//! array slices.
+
+use std::ops::Range;
+
+pub(crate) enum Placeholder<'a> {
+    /// A `:name` placeholder, without the colon.
+    Named(&'a str),
+    /// A `$N` placeholder.
+    Positional(usize),
+}
+
+fn is_ident_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
+}
+
+fn is_ident_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Whether the word ending right before `end`, ignoring whitespace, is
+/// `keyword`.
+fn follows_keyword(sql: &str, end: usize, keyword: &str) -> bool {
+    let before = sql[..end].trim_end().as_bytes();
+    let start = match before.len().checked_sub(keyword.len()) {
+        Some(start) => start,
+        None => return false,
+    };
+    before[start..].eq_ignore_ascii_case(keyword.as_bytes())
+        && (start == 0 || !is_ident_char(before[start - 1]))
+}
+
+/// Whether the `:` at `i` separates the bounds of an array slice, such as in
+/// `arr[1:n]` or `arr[:n]`, rather than starting a placeholder. That is, it
+/// follows a digit, or the `[` of a subscript other than `ARRAY[...]`.
+fn is_slice_colon(sql: &str, i: usize) -> bool {
+    let bytes = sql.as_bytes();
+    if i == 0 {
+        return false;
+    }
+    if bytes[i - 1].is_ascii_digit() {
+        return true;
+    }
+
+    let open = i - 1;
+    bytes[open] == b'['
+        && open > 0
+        && matches!(bytes[open - 1], c if is_ident_char(c) || c == b')' || c == b']')
+        && !follows_keyword(sql, open, "array")
+}
+
+/// Returns the offset right after a quoted section that starts at `start`,
+/// where a doubled quote character stands for an escaped one. With
+/// `backslash`, a backslash escapes the character following it as well.
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8, backslash: bool) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if backslash && bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// If a dollar-quoted body such as `$tag$ ... $tag$` starts at `start`,
+/// returns the offset right after it.
+fn skip_dollar_quoted(sql: &str, start: usize) -> Option<usize> {
+    let bytes = sql.as_bytes();
+    let mut i = start + 1;
+    if i < bytes.len() && is_ident_start(bytes[i]) {
+        while i < bytes.len() && is_ident_char(bytes[i]) {
+            i += 1;
+        }
+    }
+    if bytes.get(i) != Some(&b'$') {
+        return None;
+    }
+
+    let delimiter = &sql[start..i + 1];
+    match sql[i + 1..].find(delimiter) {
+        Some(end) => Some(i + 1 + end + delimiter.len()),
+        None => Some(bytes.len()),
+    }
+}
+
+/// Returns the offset right after a (possibly nested) block comment.
+fn skip_block_comment(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+enum Lexeme<'a> {
+    Placeholder(Placeholder<'a>),
+    Word(&'a str),
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    Comma,
+}
+
+/// Finds all placeholders in the SQL, along with their location in it.
+pub(crate) fn placeholders(sql: &str) -> Vec<(Range<usize>, Placeholder<'_>)> {
+    lex(sql)
+        .into_iter()
+        .filter_map(|(range, lexeme)| match lexeme {
+            Lexeme::Placeholder(placeholder) => Some((range, placeholder)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Finds the `[...]` sections that start a word, i.e. follow whitespace, `(` or
+/// the start of the SQL, so that array subscripts are not included, and that do
+/// not follow `ARRAY`, with or without whitespace. Returns the ranges of the
+/// sections, including the brackets.
+pub(crate) fn bracketed(sql: &str) -> Vec<Range<usize>> {
+    let bytes = sql.as_bytes();
+    let mut sections = vec![];
+    let mut open = vec![];
+
+    for (range, lexeme) in lex(sql) {
+        match lexeme {
+            Lexeme::OpenBracket => {
+                let starts_word = range.start == 0
+                    || bytes[range.start - 1] == b'('
+                    || bytes[range.start - 1].is_ascii_whitespace();
+                let is_array = follows_keyword(sql, range.start, "array");
+                open.push(if starts_word && !is_array { Some(range.start) } else { None });
+            }
+            Lexeme::CloseBracket => {
+                if let Some(Some(start)) = open.pop() {
+                    sections.push(start..range.end);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    sections.sort_by_key(|range| range.start);
+    sections
+}
+
+fn lex(sql: &str) -> Vec<(Range<usize>, Lexeme<'_>)> {
+    let bytes = sql.as_bytes();
+    let mut lexemes = vec![];
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' | b'"' | b'`' => {
+                i = skip_quoted(bytes, i, bytes[i], false);
+            }
+            b'e' | b'E'
+                if bytes.get(i + 1) == Some(&b'\'')
+                    && (i == 0 || !is_ident_char(bytes[i - 1])) =>
+            {
+                i = skip_quoted(bytes, i + 1, b'\'', true);
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = match sql[i..].find('\n') {
+                    Some(end) => i + end,
+                    None => bytes.len(),
+                };
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i);
+            }
+            b'$' if matches!(bytes.get(i + 1), Some(c) if c.is_ascii_digit()) => {
+                let mut end = i + 1;
+                while end < bytes.len() && bytes[end].is_ascii_digit() {
+                    end += 1;
+                }
+                let index = sql[i + 1..end].parse().unwrap_or(usize::MAX);
+                lexemes.push((i..end, Lexeme::Placeholder(Placeholder::Positional(index))));
+                i = end;
+            }
+            b'$' => {
+                i = skip_dollar_quoted(sql, i).unwrap_or(i + 1);
+            }
+            b':' if bytes.get(i + 1) == Some(&b':') => {
+                // A `::type` cast
+                i += 2;
+            }
+            b':' if is_slice_colon(sql, i) => {
+                i += 1;
+            }
+            b':' if matches!(bytes.get(i + 1), Some(&c) if is_ident_start(c)) => {
+                let mut end = i + 2;
+                while end < bytes.len() && is_ident_char(bytes[end]) {
+                    end += 1;
+                }
+                lexemes.push((i..end, Lexeme::Placeholder(Placeholder::Named(&sql[i + 1..end]))));
+                i = end;
+            }
+            b'(' | b')' | b'[' | b']' | b',' => {
+                let lexeme = match bytes[i] {
+                    b'(' => Lexeme::OpenParen,
+                    b')' => Lexeme::CloseParen,
+                    b'[' => Lexeme::OpenBracket,
+                    b']' => Lexeme::CloseBracket,
+                    _ => Lexeme::Comma,
+                };
+                lexemes.push((i..i + 1, lexeme));
+                i += 1;
+            }
+            c if is_ident_start(c) => {
+                let mut end = i + 1;
+                while end < bytes.len() && is_ident_char(bytes[end]) {
+                    end += 1;
+                }
+                lexemes.push((i..end, Lexeme::Word(&sql[i..end])));
+                i = end;
+            }
+            _ => {
+                i += 1;
+            }
+        }
+    }
+
+    lexemes
+}
+
+/// Replaces `:name` placeholders for which `f` returns a substitute, keeping
+/// the rest of the SQL intact.
+pub(crate) fn replace_named<'a, F>(sql: &'a str, mut f: F) -> String
+where
+    F: FnMut(&'a str) -> Option<String>,
+{
+    let mut result = String::new();
+    let mut last = 0;
+
+    for (range, placeholder) in placeholders(sql) {
+        if let Placeholder::Named(name) = placeholder {
+            if let Some(substitute) = f(name) {
+                result.push_str(&sql[last..range.start]);
+                result.push_str(&substitute);
+                last = range.end;
+            }
+        }
+    }
+
+    result.push_str(&sql[last..]);
+    result
+}
+
+/// If the placeholder at `range` is the only item of an `IN (...)` list,
+/// returns the range of the whole `[NOT] IN (...)`, and whether it is negated.
+pub(crate) fn in_list(sql: &str, range: Range<usize>) -> Option<(Range<usize>, bool)> {
+    let before = sql[..range.start].trim_end().strip_suffix('(')?.trim_end().as_bytes();
+    let is_keyword = |end: usize, keyword: &str| {
+        end >= keyword.len()
+            && before[end - keyword.len()..end].eq_ignore_ascii_case(keyword.as_bytes())
+            && (end == keyword.len() || !is_ident_char(before[end - keyword.len() - 1]))
+    };
+    if !is_keyword(before.len(), "in") {
+        return None;
+    }
+
+    let after = sql[range.end..].trim_start();
+    if !after.starts_with(')') {
+        return None;
+    }
+    let end = sql.len() - after.len() + 1;
+
+    let mut start = before.len() - 2;
+    let not_end = sql[..start].trim_end().len();
+    let negated = is_keyword(not_end, "not");
+    if negated {
+        start = not_end - 3;
+    }
+
+    Some((start..end, negated))
+}
+
+/// Finds the `SET` clause of an `UPDATE` statement, up to a `WHERE`, `FROM` or
+/// `RETURNING` clause. Returns the ranges of its comma-separated assignments,
+/// trimmed of whitespace.
+pub(crate) fn set_clause(sql: &str) -> Option<Vec<Range<usize>>> {
+    let mut lexemes = lex(sql).into_iter();
+    let mut depth = 0;
+
+    let start = loop {
+        match lexemes.next()? {
+            (_, Lexeme::OpenParen) => depth += 1,
+            (_, Lexeme::CloseParen) => depth -= 1,
+            (range, Lexeme::Word(word)) if depth == 0 && word.eq_ignore_ascii_case("set") => {
+                break range.end;
+            }
+            _ => {}
+        }
+    };
+
+    let mut commas = vec![];
+    let mut end = sql.len();
+    for (range, lexeme) in lexemes {
+        match lexeme {
+            Lexeme::OpenParen => depth += 1,
+            Lexeme::CloseParen => depth -= 1,
+            Lexeme::Comma if depth == 0 => commas.push(range.start),
+            Lexeme::Word(word) if depth == 0
+                && ["where", "from", "returning"].iter().any(|x| word.eq_ignore_ascii_case(x)) =>
+            {
+                end = range.start;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let starts = Some(start).into_iter().chain(commas.iter().map(|comma| comma + 1));
+    let ends = commas.iter().copied().chain(Some(end));
+    let assignments = starts
+        .zip(ends)
+        .map(|(start, end)| {
+            let text = &sql[start..end];
+            let start = start + text.len() - text.trim_start().len();
+            start..start + text.trim().len()
+        })
+        .collect();
+
+    Some(assignments)
+}
+
+/// Whether the statement has a top-level `RETURNING` clause, i.e. it is an
+/// `INSERT`, `UPDATE` or `DELETE` that returns rows.
+pub(crate) fn has_returning(sql: &str) -> bool {
+    let mut depth = 0;
+    lex(sql).into_iter().any(|(_, lexeme)| match lexeme {
+        Lexeme::OpenParen => {
+            depth += 1;
+            false
+        }
+        Lexeme::CloseParen => {
+            depth -= 1;
+            false
+        }
+        Lexeme::Word(word) => depth == 0 && word.eq_ignore_ascii_case("returning"),
+        _ => false,
+    })
+}
+
+/// Whether the result of the statement may depend on rows other than the
+/// current one, through `DISTINCT`, grouping, ordering, limits, compound
+/// `SELECT`s, window functions or aggregates. Such a statement does not give
+/// the same result when executed separately for each part of a list.
+pub(crate) fn combines_rows(sql: &str) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "distinct", "group", "having", "order", "limit", "offset", "union", "intersect",
+        "except", "over", "window",
+    ];
+    const AGGREGATES: &[&str] = &[
+        "count", "sum", "total", "avg", "min", "max", "group_concat", "string_agg",
+        "json_group_array", "json_group_object",
+    ];
+
+    let lexemes = lex(sql);
+    lexemes.iter().enumerate().any(|(idx, (_, lexeme))| match lexeme {
+        Lexeme::Word(word) => {
+            let is = |names: &[&str]| names.iter().any(|name| word.eq_ignore_ascii_case(name));
+            is(KEYWORDS)
+                || (is(AGGREGATES) && matches!(lexemes.get(idx + 1), Some((_, Lexeme::OpenParen))))
+        }
+        _ => false,
+    })
+}
+
+/// Whether the `IN (...)` at `range`, as found by `in_list`, is a condition of
+/// the top-level `WHERE` clause that is joined to the rest of it by `AND`, so
+/// that a row matches it regardless of the other parts of the list. The
+/// clause may hold `OR` only inside parentheses, and the condition may not be
+/// negated by `NOT`. The brackets of optional fragments are ignored.
+pub(crate) fn is_where_conjunct(sql: &str, range: Range<usize>) -> bool {
+    const CLAUSE_ENDS: &[&str] = &[
+        "returning", "group", "having", "order", "limit", "offset", "window", "union",
+        "intersect", "except",
+    ];
+
+    let is = |word: &str, keyword: &str| word.eq_ignore_ascii_case(keyword);
+    let mut depth = 0;
+    let mut clause = None;
+    // The top-level words of the clause, and the `AND`s joining its conditions,
+    // other than those of `BETWEEN ... AND ...`.
+    let mut words = vec![];
+    let mut ands = vec![];
+    let mut between = false;
+
+    for (lexeme_range, lexeme) in lex(sql) {
+        match lexeme {
+            Lexeme::OpenParen => depth += 1,
+            Lexeme::CloseParen => depth -= 1,
+            Lexeme::Word(word) if depth == 0 => match clause {
+                None if is(word, "where") => clause = Some(lexeme_range.end..sql.len()),
+                None => {}
+                Some(ref mut clause) if CLAUSE_ENDS.iter().any(|end| is(word, end)) => {
+                    clause.end = lexeme_range.start;
+                    break;
+                }
+                Some(_) => {
+                    if is(word, "between") {
+                        between = true;
+                    } else if is(word, "and") && between {
+                        between = false;
+                    } else if is(word, "and") {
+                        ands.push(lexeme_range.clone());
+                    }
+                    words.push((lexeme_range, word));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    let clause = match clause {
+        Some(clause) if clause.contains(&range.start) => clause,
+        _ => return false,
+    };
+    let top_level = words.iter().any(|(word_range, _)| *word_range == (range.start..range.start + 2));
+    if !top_level || words.iter().any(|(_, word)| is(word, "or")) {
+        return false;
+    }
+
+    let start = ands.iter().map(|and| and.end).filter(|end| *end <= range.start).max()
+        .unwrap_or(clause.start);
+    let end = ands.iter().map(|and| and.start).filter(|start| *start >= range.end).min()
+        .unwrap_or(clause.end);
+    let negated = words.iter().any(|(word_range, word)| {
+        word_range.start >= start && word_range.start < range.start && is(word, "not")
+    });
+    let rest = sql[range.end..end].trim_matches(|c: char| c == ']' || c.is_whitespace());
+    !negated && rest.is_empty()
+}
+
+/// A `COPY` statement, as far as it matters for `copy_in` and `copy_out` queries.
+pub(crate) struct CopyStatement {
+    /// Whether it copies `FROM STDIN`, rather than `TO STDOUT`.
+    pub(crate) from_stdin: bool,
+    /// Whether its options select the binary format.
+    pub(crate) binary: bool,
+    /// The number of columns in its column list, if it has one.
+    pub(crate) columns: Option<usize>,
+    /// A `SELECT` of the copied columns, which can be prepared to find their types.
+    pub(crate) select: String,
+}
+
+/// Parses a `COPY <table> [(<columns>)] FROM STDIN` or a
+/// `COPY {<table> [(<columns>)] | (<query>)} TO STDOUT` statement.
+pub(crate) fn copy_statement(sql: &str) -> Option<CopyStatement> {
+    let lexemes = lex(sql);
+    let copy_end = match lexemes.first()? {
+        (range, Lexeme::Word(word)) if word.eq_ignore_ascii_case("copy") => range.end,
+        _ => return None,
+    };
+
+    // The index of the parenthesis closing the one at `open`, and the number
+    // of commas between them.
+    let closing = |open: usize| {
+        let mut depth = 0;
+        let mut commas = 0;
+        for (idx, (_, lexeme)) in lexemes.iter().enumerate().skip(open) {
+            match lexeme {
+                Lexeme::OpenParen => depth += 1,
+                Lexeme::CloseParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((idx, commas));
+                    }
+                }
+                Lexeme::Comma if depth == 1 => commas += 1,
+                _ => {}
+            }
+        }
+        None
+    };
+
+    let mut idx = 1;
+    let mut columns = None;
+    let select = match lexemes.get(idx)? {
+        (range, Lexeme::OpenParen) if sql[copy_end..range.start].trim().is_empty() => {
+            let (close, _) = closing(idx)?;
+            idx = close + 1;
+            sql[range.end..lexemes[close].0.start].trim().to_owned()
+        }
+        _ => {
+            let end = lexemes[idx..].iter().position(|(_, lexeme)| match lexeme {
+                Lexeme::OpenParen => true,
+                Lexeme::Word(word) => word.eq_ignore_ascii_case("from") || word.eq_ignore_ascii_case("to"),
+                _ => false,
+            })? + idx;
+            let table = sql[copy_end..lexemes[end].0.start].trim();
+            if table.is_empty() {
+                return None;
+            }
+            idx = end;
+
+            let list = match &lexemes[end] {
+                (range, Lexeme::OpenParen) => {
+                    let (close, commas) = closing(end)?;
+                    idx = close + 1;
+                    let list = sql[range.end..lexemes[close].0.start].trim();
+                    columns = Some(if list.is_empty() { 0 } else { commas + 1 });
+                    list
+                }
+                _ => "*",
+            };
+            format!("SELECT {} FROM {}", list, table)
+        }
+    };
+
+    let word = |idx: usize| match lexemes.get(idx) {
+        Some((_, Lexeme::Word(word))) => Some(word.to_ascii_lowercase()),
+        _ => None,
+    };
+    let from_stdin = match (word(idx)?.as_str(), word(idx + 1)?.as_str()) {
+        ("from", "stdin") => true,
+        ("to", "stdout") => false,
+        _ => return None,
+    };
+    let binary = (idx + 2..lexemes.len()).any(|idx| word(idx).as_deref() == Some("binary"));
+
+    Some(CopyStatement { from_stdin, binary, columns, select })
+}
+
+/// Rewrites `[NOT] IN (<placeholder>)` to `= ANY(<placeholder>)` or
+/// `<> ALL(<placeholder>)` for the placeholders selected by `is_list`, so that
+/// a list can be bound as a single array.
+pub(crate) fn replace_in_lists<F>(sql: &str, is_list: F) -> String
+where
+    F: Fn(&Placeholder<'_>) -> bool,
+{
+    let mut result = String::new();
+    let mut last = 0;
+
+    for (range, placeholder) in placeholders(sql) {
+        if !is_list(&placeholder) {
+            continue;
+        }
+        if let Some((list_range, negated)) = in_list(sql, range.clone()) {
+            result.push_str(&sql[last..list_range.start]);
+            result.push_str(if negated { "<> ALL(" } else { "= ANY(" });
+            result.push_str(&sql[range]);
+            result.push(')');
+            last = list_range.end;
+        }
+    }
+
+    result.push_str(&sql[last..]);
+    result
+}
+
+/// Names of all `:name` placeholders, in order of appearance.
+pub(crate) fn named_placeholders(sql: &str) -> Vec<&str> {
+    placeholders(sql)
+        .into_iter()
+        .filter_map(|(_, placeholder)| match placeholder {
+            Placeholder::Named(name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Indices of all `$N` placeholders, in order of appearance.
+pub(crate) fn positional_placeholders(sql: &str) -> Vec<usize> {
+    placeholders(sql)
+        .into_iter()
+        .filter_map(|(_, placeholder)| match placeholder {
+            Placeholder::Positional(index) => Some(index),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named() {
+        assert_eq!(named_placeholders("SELECT :a, :b_1 FROM t WHERE x = :a"), ["a", "b_1", "a"]);
+        assert_eq!(named_placeholders("SELECT a FROM t WHERE x IN (:ids)"), ["ids"]);
+        assert!(named_placeholders("SELECT ':1', :2").is_empty());
+    }
+
+    #[test]
+    fn positional() {
+        assert_eq!(positional_placeholders("SELECT $1, $12 FROM t WHERE x = $1"), [1, 12, 1]);
+        assert!(named_placeholders("SELECT $1").is_empty());
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(named_placeholders("SELECT ':a', \"b:c\", `d:e`, :f"), ["f"]);
+        assert_eq!(named_placeholders("SELECT 'it''s :a', :b"), ["b"]);
+        assert_eq!(named_placeholders("SELECT 'a\\', :b"), ["b"]);
+        assert!(named_placeholders("SELECT ':a").is_empty());
+    }
+
+    #[test]
+    fn escape_strings() {
+        assert_eq!(named_placeholders("SELECT E'\\' :a', :b"), ["b"]);
+        assert_eq!(named_placeholders("SELECT e'it''s :a', :b"), ["b"]);
+        // Not an escape string, so the backslash does not escape the quote
+        assert_eq!(named_placeholders("SELECT name'\\', :b"), ["b"]);
+    }
+
+    #[test]
+    fn dollar_quotes() {
+        assert_eq!(named_placeholders("SELECT $$ :a ' $$, :b"), ["b"]);
+        assert_eq!(named_placeholders("SELECT $tag$ :a $$ :b $tag$, :c"), ["c"]);
+        assert_eq!(positional_placeholders("SELECT $body$ $1 $body$, $2"), [2]);
+        assert!(named_placeholders("SELECT $$ :a").is_empty());
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(named_placeholders("SELECT :a -- :b\n, :c"), ["a", "c"]);
+        assert_eq!(named_placeholders("SELECT /* :a /* :b */ :c */ :d"), ["d"]);
+        assert!(named_placeholders("SELECT /* /* */ :a").is_empty());
+    }
+
+    #[test]
+    fn casts() {
+        assert_eq!(named_placeholders("SELECT :a::int, b::text, :c :: int"), ["a", "c"]);
+        assert_eq!(positional_placeholders("SELECT $1::int"), [1]);
+    }
+
+    #[test]
+    fn brackets() {
+        let sections = |sql| {
+            bracketed(sql).into_iter().map(|range| &sql[range]).collect::<Vec<_>>()
+        };
+        assert_eq!(sections("WHERE 1 = 1 [AND a = :a] [AND b > :b]"), ["[AND a = :a]", "[AND b > :b]"]);
+        assert_eq!(sections("WHERE ([a = :a] OR x) [AND c[1] = :c]"), ["[a = :a]", "[AND c[1] = :c]"]);
+        assert!(sections("SELECT arr[:i], ARRAY[:a], array [:b], ARRAY\n[1, 2]").is_empty());
+        assert_eq!(sections("WHERE '[x]' = :x [AND tags = ARRAY [:tags]]"), ["[AND tags = ARRAY [:tags]]"]);
+    }
+
+    #[test]
+    fn row_combining() {
+        assert!(!combines_rows("SELECT name FROM pet WHERE id IN (:ids)"));
+        assert!(!combines_rows("DELETE FROM pet WHERE id IN (:ids) RETURNING id"));
+        assert!(!combines_rows("SELECT count FROM pet WHERE id IN (:ids) AND 'order' = :o"));
+        assert!(combines_rows("SELECT count(*) FROM pet WHERE id IN (:ids)"));
+        assert!(combines_rows("SELECT MAX (id) FROM pet WHERE id IN (:ids)"));
+        assert!(combines_rows("SELECT DISTINCT name FROM pet WHERE id IN (:ids)"));
+        assert!(combines_rows("SELECT name FROM pet WHERE id IN (:ids) ORDER BY name"));
+        assert!(combines_rows("SELECT name FROM pet WHERE id IN (:ids) LIMIT 1"));
+        assert!(combines_rows("SELECT id FROM pet WHERE id IN (:ids) UNION SELECT 0"));
+    }
+
+    #[test]
+    fn where_conjuncts() {
+        let conjunct = |sql: &str| {
+            let (range, _) = placeholders(sql).into_iter().next().unwrap();
+            let (range, _) = in_list(sql, range).unwrap();
+            is_where_conjunct(sql, range)
+        };
+        assert!(conjunct("SELECT name FROM pet WHERE id IN (:ids)"));
+        assert!(conjunct("SELECT name FROM pet WHERE x = 1 AND id IN (:ids) AND (a OR b)"));
+        assert!(conjunct("SELECT name FROM pet WHERE x BETWEEN 1 AND 2 AND id IN (:ids)"));
+        assert!(conjunct("UPDATE pet SET data = NULL WHERE id IN (:ids) RETURNING id"));
+        assert!(conjunct("SELECT name FROM pet WHERE 1 = 1 [AND id IN (:ids)]"));
+        assert!(!conjunct("SELECT name FROM pet WHERE id IN (:ids) OR name = :name"));
+        assert!(!conjunct("UPDATE pet SET n = n + 1 WHERE flag or id IN (:ids)"));
+        assert!(!conjunct("SELECT name FROM pet WHERE (id IN (:ids) OR x) AND y"));
+        assert!(!conjunct("SELECT name FROM pet WHERE 1 = 1 [OR id IN (:ids)]"));
+        assert!(!conjunct("SELECT name FROM pet WHERE NOT id IN (:ids)"));
+        assert!(!conjunct("SELECT name FROM pet WHERE id IN (:ids) = x"));
+        assert!(!conjunct("SELECT id IN (:ids) FROM pet"));
+        assert!(!conjunct("SELECT * FROM pet WHERE x IN (SELECT id FROM t WHERE id IN (:ids))"));
+    }
+
+    #[test]
+    fn slices() {
+        assert!(named_placeholders("SELECT arr[1:n], arr[:n], arr[1:3] FROM t").is_empty());
+        assert!(named_placeholders("SELECT (arr)[:n], arr[1][:n]").is_empty());
+        assert_eq!(named_placeholders("SELECT arr[:i], ARRAY[:a], array [:b]"), ["a", "b"]);
+        assert_eq!(named_placeholders("SELECT x FROM t WHERE 1 = 1 [AND y = :y]"), ["y"]);
+    }
+}
@@ -0,0 +1,236 @@
+//! Loading of queries from `.sql` files for `fnsql_file!`, where each query
+//! is introduced by yesql-style header comments:
+//!
+//! ```sql
+//! -- name: get_pet_id_data(name: Option<String>) -> [(i32, Option<Vec<u8>>)]
+//! -- attrs: rusqlite, test(with=[create_table_pet])
+//! SELECT id, data FROM pet WHERE pet.name = :name
+//! ```
+//!
+//! The header is parsed just like the signature of a query in `fnsql!`, and
+//! the lines up to the next `-- name:` header are the query's SQL. Other
+//! comment lines right after the header are the query's doc comment.
+//! `-- attrs:` headers before the first query are block-level defaults, like
+//! `#![...]`.
+
+use std::path::Path;
+
+use proc_macro2::TokenStream as Tokens;
+use quote::quote;
+use syn::{parse::Parser, LitStr};
+
+use crate::query::{Defaults, Origin, Query};
+
+/// If the line is a `-- <key>: <value>` header comment, returns the key and
+/// the value.
+fn header(line: &str) -> Option<(&str, &str)> {
+    let comment = line.trim().strip_prefix("--")?.trim_start();
+    ["name", "attrs"].iter().find_map(|key| {
+        let value = comment.strip_prefix(key)?.strip_prefix(':')?;
+        Some((*key, value.trim()))
+    })
+}
+
+struct Entry {
+    /// Line number of the `-- name:` header.
+    line: usize,
+    signature: String,
+    attrs: Vec<String>,
+    docs: Vec<String>,
+    sql: Vec<String>,
+}
+
+struct File {
+    /// Line number of the first `-- attrs:` header of the defaults.
+    defaults_line: usize,
+    defaults: Vec<String>,
+    entries: Vec<Entry>,
+}
+
+fn split(contents: &str) -> Result<File, (usize, String)> {
+    let mut defaults_line = 0;
+    let mut defaults = vec![];
+    let mut entries: Vec<Entry> = vec![];
+    let mut in_header = false;
+
+    for (idx, line) in contents.lines().enumerate() {
+        let line_nr = idx + 1;
+
+        match header(line) {
+            Some(("name", signature)) => {
+                entries.push(Entry {
+                    line: line_nr,
+                    signature: signature.to_owned(),
+                    attrs: vec![],
+                    docs: vec![],
+                    sql: vec![],
+                });
+                in_header = true;
+            }
+            Some((_, attrs)) => match entries.last_mut() {
+                Some(entry) if in_header => entry.attrs.push(attrs.to_owned()),
+                Some(_) => return Err((line_nr, "`-- attrs:` must follow a `-- name:` header".to_owned())),
+                None => {
+                    if defaults.is_empty() {
+                        defaults_line = line_nr;
+                    }
+                    defaults.push(attrs.to_owned());
+                }
+            },
+            None if in_header && line.trim().starts_with("--") => {
+                if let Some(entry) = entries.last_mut() {
+                    entry.docs.push(line.trim()[2..].to_owned());
+                }
+            }
+            None => {
+                in_header = false;
+                match entries.last_mut() {
+                    Some(entry) => entry.sql.push(line.to_owned()),
+                    None if line.trim().is_empty() || line.trim().starts_with("--") => {}
+                    None => return Err((line_nr, "SQL before the first `-- name:` header".to_owned())),
+                }
+            }
+        }
+    }
+
+    Ok(File { defaults_line, defaults, entries })
+}
+
+impl Entry {
+    fn parse(&self, path: &LitStr, location: String, defaults: &Defaults) -> syn::Result<Vec<Query>> {
+        let sql = self.sql.join("\n");
+        let sql = sql.trim();
+        if sql.is_empty() {
+            return Err(syn::Error::new(path.span(),
+                format!("{}: the query has no SQL", location)));
+        }
+
+        let attrs = if self.attrs.is_empty() {
+            String::new()
+        } else {
+            format!("#[{}]", self.attrs.join(", "))
+        };
+
+        let sql = LitStr::new(sql, path.span());
+        let docs = &self.docs;
+        let parsed = syn::parse_str::<Tokens>(&format!("{} {}", attrs, self.signature))
+            .and_then(|header| {
+                let parser = |input: syn::parse::ParseStream| Query::parse_with_defaults(input, defaults);
+                parser.parse2(quote! { #(#[doc = #docs])* #header { #sql } })
+            });
+
+        match parsed {
+            Ok(mut queries) => {
+                for query in &mut queries {
+                    query.origin = Some(Origin { span: path.span(), location: location.clone() });
+                }
+                Ok(queries)
+            }
+            Err(err) => Err(syn::Error::new(path.span(), format!("{}: {}", location, err))),
+        }
+    }
+}
+
+/// Loads the queries of a file, given relative to the crate directory.
+pub(crate) fn load(crate_dir: &Path, path: &LitStr) -> syn::Result<Vec<Query>> {
+    let name = path.value();
+    let contents = std::fs::read_to_string(crate_dir.join(&name)).map_err(|err| {
+        syn::Error::new(path.span(), format!("unable to read {}: {}", name, err))
+    })?;
+
+    let file = split(&contents).map_err(|(line, message)| {
+        syn::Error::new(path.span(), format!("{}:{}: {}", name, line, message))
+    })?;
+
+    let defaults = if file.defaults.is_empty() {
+        Defaults::default()
+    } else {
+        syn::parse_str(&format!("#![{}]", file.defaults.join(", "))).map_err(|err| {
+            syn::Error::new(path.span(), format!("{}:{}: {}", name, file.defaults_line, err))
+        })?
+    };
+
+    let mut queries = vec![];
+    let mut errors = vec![];
+
+    for entry in &file.entries {
+        match entry.parse(path, format!("{}:{}", name, entry.line), &defaults) {
+            Ok(instances) => queries.extend(instances),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    let mut errors = errors.into_iter();
+    match errors.next() {
+        None => Ok(queries),
+        Some(mut error) => {
+            error.extend(errors);
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sql(entry: &Entry) -> String {
+        entry.sql.join("\n").trim().to_owned()
+    }
+
+    fn error(contents: &str) -> Option<(usize, String)> {
+        split(contents).err()
+    }
+
+    #[test]
+    fn headers() {
+        let file = split("-- attrs: rusqlite\n\n\
+            -- name: a()\n-- attrs: script\nSELECT 1\n\n\
+            -- name:b() -> [(i32)]\nSELECT 2").unwrap();
+        assert_eq!((file.defaults_line, file.defaults), (1, vec!["rusqlite".to_owned()]));
+        assert_eq!(file.entries.len(), 2);
+        assert_eq!((file.entries[0].line, file.entries[0].signature.as_str()), (3, "a()"));
+        assert_eq!(file.entries[0].attrs, ["script"]);
+        assert_eq!(sql(&file.entries[0]), "SELECT 1");
+        assert_eq!((file.entries[1].line, file.entries[1].signature.as_str()), (7, "b() -> [(i32)]"));
+        assert_eq!(sql(&file.entries[1]), "SELECT 2");
+    }
+
+    #[test]
+    fn missing_headers() {
+        assert_eq!(error("SELECT 1\n-- name: a()\nSELECT 2"),
+            Some((1, "SQL before the first `-- name:` header".to_owned())));
+        assert_eq!(error("-- name: a()\nSELECT 1\n-- attrs: script"),
+            Some((3, "`-- attrs:` must follow a `-- name:` header".to_owned())));
+        assert!(split("").unwrap().entries.is_empty());
+        assert!(split("-- Only comments\n\n-- here").unwrap().entries.is_empty());
+    }
+
+    #[test]
+    fn comments() {
+        let file = split("-- A file comment\n-- name: a()\n-- The doc\n--  comment\n\
+            SELECT 1 -- trailing\n-- inner\nFROM t").unwrap();
+        assert_eq!(file.entries[0].docs, [" The doc", "  comment"]);
+        assert_eq!(sql(&file.entries[0]), "SELECT 1 -- trailing\n-- inner\nFROM t");
+        assert_eq!(split("-- name is not a header\n").unwrap().entries.len(), 0);
+    }
+
+    #[test]
+    fn unterminated() {
+        let file = split("-- name: a()\n-- name: b()\nSELECT 1").unwrap();
+        assert_eq!(sql(&file.entries[0]), "");
+        assert_eq!(sql(&file.entries[1]), "SELECT 1");
+
+        let path = LitStr::new("q.sql", proc_macro2::Span::call_site());
+        let err = file.entries[0].parse(&path, "q.sql:1".to_owned(), &Defaults::default()).err();
+        assert_eq!(err.map(|err| err.to_string()), Some("q.sql:1: the query has no SQL".to_owned()));
+    }
+
+    #[test]
+    fn string_literals() {
+        let script = "INSERT INTO t VALUES ('a;b');\nINSERT INTO t VALUES ('-- name: c()');";
+        let file = split(&format!("-- name: a()\n-- attrs: script\n{}\n", script)).unwrap();
+        assert_eq!(file.entries.len(), 1);
+        assert_eq!(sql(&file.entries[0]), script);
+    }
+}
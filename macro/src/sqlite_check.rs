@@ -0,0 +1,117 @@
+//! Compile-time validation of `rusqlite` queries against an in-memory
+//! database.
+//!
+//! The schema is built from the queries that others depend on for testing,
+//! or that are marked with the `schema` attribute. All other queries are then
+//! prepared against it, and their columns are counted unless their output is a
+//! `FromRow` type.
+
+use std::collections::HashSet;
+
+use crate::query::{Kind, Query};
+
+struct Checker<'a> {
+    queries: &'a [Query],
+    conn: rusqlite::Connection,
+    applied: HashSet<String>,
+    errors: Vec<syn::Error>,
+}
+
+impl<'a> Checker<'a> {
+    fn find(&self, name: &str) -> Option<&'a Query> {
+        self.queries.iter().find(|query| is_rusqlite(query) && query.name == name)
+    }
+
+    fn apply(&mut self, query: &'a Query) {
+        if !self.applied.insert(query.name.to_string()) {
+            return;
+        }
+
+        for depend in query.test.iter().flatten() {
+            if let Some(depend) = self.find(depend) {
+                self.apply(depend);
+            }
+        }
+
+        if query.unchecked {
+            return;
+        }
+
+        // Queries with parameters cannot be executed here, so they are
+        // only verified.
+        let result = if query.params.is_empty() {
+            self.conn.execute_batch(&query.full_sql())
+        } else {
+            self.conn.prepare(&query.full_sql()).map(|_| ())
+        };
+
+        if let Err(err) = result {
+            self.errors.push(query.error(&query.query,
+                format!("SQLite rejected the query: {}", err)));
+        }
+    }
+
+    fn verify(&mut self, query: &Query) {
+        if query.unchecked {
+            return;
+        }
+
+        let stmt = match self.conn.prepare(&query.full_sql()) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                self.errors.push(query.error(&query.query,
+                    format!("SQLite rejected the query: {}", err)));
+                return;
+            }
+        };
+
+        // The fields of a `FromRow` output are not visible to the macro, so only
+        // declared output columns can be counted.
+        if !query.outputs.is_empty() && stmt.column_count() != query.outputs.len() {
+            self.errors.push(query.error(&query.query, format!(
+                "the query returns {} columns, but {} output columns are declared",
+                stmt.column_count(), query.outputs.len())));
+        }
+    }
+}
+
+fn is_rusqlite(query: &Query) -> bool {
+    matches!(query.kind, Kind::Rusqlite)
+}
+
+pub(crate) fn check(queries: &[Query]) -> Vec<syn::Error> {
+    let conn = match rusqlite::Connection::open_in_memory() {
+        Ok(conn) => conn,
+        Err(err) => {
+            return vec![syn::Error::new(proc_macro2::Span::call_site(),
+                format!("unable to open an in-memory SQLite database: {}", err))];
+        }
+    };
+
+    let mut checker = Checker {
+        queries,
+        conn,
+        applied: HashSet::new(),
+        errors: vec![],
+    };
+
+    let depends: HashSet<String> = queries
+        .iter()
+        .filter(|query| is_rusqlite(query))
+        .flat_map(|query| query.test.iter().flatten().cloned())
+        .collect();
+
+    for query in queries.iter().filter(|query| is_rusqlite(query)) {
+        if query.schema || query.script || depends.contains(&query.name.to_string()) {
+            checker.apply(query);
+        }
+    }
+
+    for query in queries.iter().filter(|query| is_rusqlite(query)) {
+        if !checker.applied.contains(&query.name.to_string()) {
+            checker.verify(query);
+        }
+    }
+
+    checker.errors
+}
@@ -0,0 +1,24 @@
+-- Pet queries, loaded by `fnsql_file!` in src/sqlite_file.rs
+-- attrs: rusqlite, test(with=[create_table_pet])
+
+-- name: create_table_pet()
+-- attrs: test(with=[])
+CREATE TABLE pet (
+      id      INTEGER PRIMARY KEY,
+      name    TEXT NOT NULL,
+      data    BLOB
+)
+
+-- name: insert_new_pet(name: str, data: Option<Vec<u8>>)
+INSERT INTO pet (name, data) VALUES (:name, :data)
+
+-- name: get_pet_id_data(name: Option<String>) -> [(i32, Option<Vec<u8>>)]
+-- Pets are looked up by name.
+SELECT id, data FROM pet WHERE pet.name = :name
+
+-- name: get_pets(name: str) -> [(id: i32, name: String)]
+-- attrs: serde
+SELECT id, name
+  FROM pet
+ -- Names are unique enough
+ WHERE name = :name
@@ -1,9 +1,15 @@
 extern crate postgres as crate_postgres;
 
 mod sqlite;
+mod sqlite_file;
+mod sqlite_mod;
 mod postgres;
+mod multi;
 
 fn main() {
     sqlite::main().unwrap();
+    sqlite_file::main().unwrap();
+    sqlite_mod::main().unwrap();
     postgres::main().unwrap();
+    multi::main().unwrap();
 }
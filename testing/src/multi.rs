@@ -0,0 +1,62 @@
+fnsql::fnsql! {
+    #![rusqlite, postgres, test(with=[create_table_owner])]
+
+    #[test(with=[])]
+    create_table_owner() {
+        "CREATE TABLE owner (
+              id      INTEGER PRIMARY KEY,
+              name    TEXT NOT NULL
+        )"
+    }
+
+    insert_owner(id: i32, name: str) {
+        "INSERT INTO owner (id, name) VALUES (:id, :name)"
+    }
+
+    insert_owner_once(id: i32, name: str) {
+        rusqlite: "INSERT OR IGNORE INTO owner (id, name) VALUES (:id, :name)",
+        postgres: "INSERT INTO owner (id, name) VALUES (:id, :name) ON CONFLICT DO NOTHING",
+    }
+
+    get_owners(name: str) -> [(id: i32, name: String)] {
+        "SELECT id, name FROM owner WHERE name = :name OR :name = ''"
+    }
+
+    get_owner(id: i32) -> Option<(name: String)> {
+        "SELECT name FROM owner WHERE id = :id"
+    }
+
+    #[rusqlite]
+    get_owner_count() -> i64 {
+        "SELECT count(*) FROM owner"
+    }
+}
+
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let conn = rusqlite::Connection::open_in_memory()?;
+    conn.execute_create_table_owner()?;
+    conn.execute_insert_owner(&1, "Alice")?;
+    conn.execute_insert_owner_once(&1, "Bob")?;
+
+    let mut stmt = conn.prepare_get_owners()?;
+    for owner in stmt.query("Alice")? {
+        let owner: Row_get_owners = owner?;
+        println!("Found owner {} {:?}", owner.id, owner.name);
+    }
+    println!("Found {} owners", conn.query_scalar_get_owner_count()?);
+    println!("Found owner {:?}", conn.query_opt_get_owner(&1)?.map(|owner| owner.name));
+
+    let mut client = fnsql::postgres::testing_client()?;
+    client.execute("SET search_path TO pg_temp", &[])?;
+    client.execute_create_table_owner()?;
+    client.execute_insert_owner(&1, "Alice")?;
+    client.execute_insert_owner_once(&1, "Bob")?;
+
+    for owner in client.query_get_owners("")? {
+        let owner: Row_get_owners = owner;
+        println!("Found owner {} {:?}", owner.id, owner.name);
+    }
+    println!("Found owner {:?}", client.query_opt_get_owner(&2)?.map(|owner| owner.name));
+
+    Ok(())
+}
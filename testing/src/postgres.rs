@@ -1,11 +1,12 @@
 fnsql::fnsql! {
-    #[postgres, test]
+    #[postgres, script, test]
     create_table_pet() {
         "CREATE TABLE pet (
               id      INTEGER PRIMARY KEY,
               name    TEXT NOT NULL,
               data    BYTEA
-        )"
+        );
+        CREATE INDEX pet_name ON pet (name);"
     }
 
     #[postgres, test(with=[create_table_pet])]
@@ -13,23 +14,103 @@ fnsql::fnsql! {
         "SELECT id, data FROM pet WHERE pet.name = $1"
     }
 
+    #[postgres, test(with=[create_table_pet])]
+    get_pets(name: str) -> [(id: i32, name: String, data: Option<Vec<u8>>)] {
+        "SELECT id, name, data FROM pet WHERE pet.name = $1"
+    }
+
+    #[postgres, test(with=[create_table_pet])]
+    get_pet_structs(name: str) -> [Pet] {
+        "SELECT id, name, data FROM pet WHERE pet.name = $1"
+    }
+
     #[postgres, named, test(with=[create_table_pet])]
     insert_new_pet(id: i32, name: String, data: Option<Vec<u8>>) {
         "INSERT INTO pet (id, name, data) VALUES (:id, :name, :data)"
     }
 
+    #[postgres, named, test(with=[create_table_pet])]
+    get_pet_names_cast(name: str) -> [(String)] {r#"
+        -- Names are compared as :name, after a cast
+        SELECT name::text
+          FROM pet
+         WHERE name = :name::text AND name <> 'a:b' AND name <> E'\':c' AND name <> $$:d$$
+    "#}
+
     #[postgres, test(with=[create_table_pet])]
     insert_new_pet_str(id: i32, name: str, data: Option<Vec<u8>>) {
         "INSERT INTO pet (id, name, data) VALUES ($1, $2, $3)"
     }
 
+    #[postgres, many, test(with=[create_table_pet])]
+    insert_pet(pet: &Pet { id, name, data }) {
+        "INSERT INTO pet (id, name, data) VALUES ($1, $2, $3)"
+    }
+
     #[postgres, test(with=[create_table_pet])]
     update_pet_data(name: str, data: [u8]) {
         "UPDATE pet SET data = $2 WHERE name = $1"
     }
 
+    /// Writes pets in the binary `COPY` format.
+    #[postgres, copy_in, test(with=[create_table_pet])]
+    pet_rows(id: i32, name: str, data: Option<Vec<u8>>) {
+        "COPY pet (id, name, data) FROM STDIN BINARY"
+    }
+
+    #[postgres, copy_out, test(with=[create_table_pet])]
+    pet_names() -> [(id: i32, name: String)] {
+        "COPY (SELECT id, name FROM pet ORDER BY id) TO STDOUT (FORMAT binary)"
+    }
+
+    /// Changes of pets, for invalidating cached ones.
+    #[postgres, channel = json, test]
+    pet_changed(event: PetEvent);
+
+    #[postgres, channel, test]
+    pets_cleared(reason: str) {
+        "pets-cleared"
+    }
+
+    #[postgres, expect_rows = 0..=1, test(with=[create_table_pet])]
+    delete_pet(id: i32) {
+        "DELETE FROM pet WHERE id = $1"
+    }
+
+    #[postgres, test(with=[create_table_pet])]
+    get_pet_by_id(id: i32) -> (String, Option<Vec<u8>>) {
+        "SELECT name, data FROM pet WHERE id = $1"
+    }
+
+    #[postgres, named, test(with=[create_table_pet])]
+    count_pets(name: Option<String>) -> i64 {
+        "SELECT count(*) FROM pet WHERE true [AND name = :name]"
+    }
+
+    #[postgres, test(with=[create_table_pet])]
+    get_pet_names_by_ids(ids: [i32] as list) -> [(String)] {
+        "SELECT name FROM pet WHERE id IN ($1) ORDER BY id"
+    }
+
+    #[postgres, test(with=[create_table_pet])]
+    get_pet_name_structs() -> [PetName] {
+        "SELECT name FROM pet ORDER BY id"
+    }
+
+    #[postgres, named, test(with=[create_table_pet])]
+    find_pets(name: Option<String>, min_id: Option<i32>) -> [Pet] {
+        "SELECT id, name, data FROM pet WHERE true [AND name = :name] [AND id >= :min_id]"
+    }
+
+    #[postgres, named, update, test(with=[create_table_pet])]
+    update_pet(id: i32, name: Option<String>, data: Option<Option<Vec<u8>>>) {
+        "UPDATE pet SET name = :name, data = :data WHERE id = :id"
+    }
+
+    /// Counts the pets having the given id.
     #[postgres, test(with=[create_table_pet])]
-    get_pet_count(pet_id: i32) -> [(i32)] {r#"
+    #[cfg(not(target_os = "emscripten"))]
+    get_pet_count(pet_id: i32) -> i64 {r#"
          SELECT count(*)
            FROM pet
           WHERE id = $1
@@ -37,19 +118,44 @@ fnsql::fnsql! {
 }
 
 
-#[derive(Debug)]
-struct Pet {
+#[derive(Debug, fnsql::FromRow)]
+#[fnsql(postgres)]
+#[cfg_attr(test, derive(arbitrary::Arbitrary))]
+pub struct Pet {
     id: i32,
     name: String,
     data: Option<Vec<u8>>,
 }
 
-pub fn main() -> Result<(), postgres::Error> {
+/// A non-empty pet name, whose `FromRow` fails with an error of its own.
+#[derive(Debug)]
+pub struct PetName(String);
+
+impl fnsql::FromRow<postgres::Row> for PetName {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_row(row: &postgres::Row) -> Result<Self, Self::Error> {
+        let name: String = row.try_get(0)?;
+        if name.is_empty() {
+            return Err("empty pet name".into());
+        }
+        Ok(PetName(name))
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(test, derive(arbitrary::Arbitrary))]
+pub struct PetEvent {
+    id: i32,
+    deleted: bool,
+}
+
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut conn = fnsql::postgres::testing_client()?;
     conn.execute("SET search_path TO pg_temp", &[]).unwrap();
     conn.execute("CREATE TYPE foo AS ENUM ('Bar', 'Baz')", &[]).unwrap();
 
-    conn.execute_create_table_pet()?;
+    conn.execute_batch_create_table_pet()?;
 
     let mut me = Pet {
         id: 0,
@@ -68,5 +174,77 @@ pub fn main() -> Result<(), postgres::Error> {
     let prep = conn.prepare_cached_insert_new_pet(&mut cache)?;
     conn.execute_prepared_insert_new_pet(&prep, &me.id, &me.name, &me.data)?;
 
+    me.id += 1;
+    conn.execute_insert_pet(&me)?;
+
+    let pets: Vec<_> = (10..13).map(|id| Pet { id, name: format!("Pet {}", id), data: None }).collect();
+    println!("Inserted {} pets", conn.insert_many_insert_pet(&pets)?);
+
+    let mut writer = conn.copy_in_pet_rows()?;
+    for id in 20..23 {
+        writer.write(&id, &format!("Pet {}", id), &None)?;
+    }
+    println!("Copied {} pets in", writer.finish()?);
+
+    for row in conn.copy_out_pet_names()? {
+        let row = row?;
+        println!("Copied pet {} {:?} out", row.id, row.name);
+    }
+
+    for pet in conn.query_get_pets("Max")? {
+        println!("Found pet {} {:?} {:?}", pet.id, pet.name, pet.data);
+    }
+
+    for name in conn.query_get_pet_names_cast("Max")? {
+        println!("Found pet name {:?}", name);
+    }
+
+    for pet in conn.query_get_pet_structs("Max")? {
+        println!("Found pet {:?}", pet);
+    }
+
+    println!("Found pet names {:?}", conn.query_get_pet_names_by_ids(&[0, 2])?);
+
+    for pet in conn.query_find_pets(&Some("Max".to_string()), &Some(1))? {
+        println!("Found pet {:?}", pet);
+    }
+    println!("Found pet {:?}", conn.query_opt_find_pets(&None, &Some(22))?);
+
+    for name in conn.query_get_pet_name_structs()? {
+        println!("Found pet name {}", name.0);
+    }
+    conn.execute_insert_new_pet(&30, &String::new(), &None)?;
+    assert!(matches!(conn.query_get_pet_name_structs(), Err(fnsql::Error::Row(_))));
+
+    let updated = conn.execute_update_pet(&2, &Some("Bella".to_string()), &None);
+    println!("Updated {:?} pets", updated.ok());
+
+    let pet = conn.query_one_get_pets("Max");
+    println!("Found pet {:?}", pet.map(|pet| pet.id));
+
+    let (name, data) = conn.query_one_get_pet_by_id(&0)?;
+    println!("Found pet {:?} {:?}", name, data);
+    println!("Found {} pets", conn.query_scalar_get_pet_count(&0)?);
+    let prep = conn.prepare_delete_pet()?;
+    println!("Deleted {} pets", conn.execute_prepared_delete_pet(&prep, &3)?);
+    println!("Deleted {} pets", conn.execute_delete_pet(&3)?);
+
+    conn.listen_pet_changed()?;
+    conn.listen_pets_cleared()?;
+    conn.notify_pet_changed(&PetEvent { id: 1, deleted: false })?;
+    for event in Notifications_pet_changed(conn.notifications().iter()) {
+        println!("Pet changed {:?}", event?);
+    }
+    conn.notify_pets_cleared("done")?;
+    for reason in Notifications_pets_cleared(conn.notifications().iter()) {
+        println!("Pets cleared {:?}", reason?);
+    }
+    conn.unlisten_pets_cleared()?;
+
+    match conn.query_scalar_count_pets(&None) {
+        Ok(count) => println!("Found {} pets", count),
+        Err(err) => println!("Unexpected error {}", err),
+    }
+
     Ok(())
 }
@@ -0,0 +1,21 @@
+fnsql::fnsql_file!("queries/pets.sql");
+
+pub fn main() -> rusqlite::Result<()> {
+    let conn = rusqlite::Connection::open_in_memory()?;
+
+    conn.execute_create_table_pet()?;
+    conn.execute_insert_new_pet("Max", &None)?;
+
+    let mut stmt = conn.prepare_get_pet_id_data()?;
+    for pet in stmt.query(&Some("Max".to_string()))? {
+        println!("Found pet {:?}", pet?);
+    }
+
+    let mut stmt = conn.prepare_get_pets()?;
+    for pet in stmt.query("Max")? {
+        let pet = pet?;
+        println!("Found pet {} {:?}", pet.id, pet.name);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,361 @@
+fnsql::fnsql! {
+    mod pets {
+        #![rusqlite, test(with=[create_table_pet])]
+
+        #[script, test(with=[])]
+        create_table_pet() {
+            "CREATE TABLE pet (
+                  id      INTEGER PRIMARY KEY,
+                  name    TEXT NOT NULL,
+                  data    BLOB
+            );
+            CREATE INDEX pet_name ON pet (name);"
+        }
+
+        #[test(with=[])]
+        sqlite_version() -> String {
+            "SELECT sqlite_version()"
+        }
+
+        get_pet_id_data(name: Option<String>) -> [(i32, Option<Vec<u8>>)] {
+            "SELECT id, data FROM pet WHERE pet.name = :name"
+        }
+
+        #[serde]
+        get_pets(name: Option<String>) -> [(id: i32, name: String, data: Option<Vec<u8>>)] {
+            "SELECT id, name, data FROM pet WHERE pet.name = :name"
+        }
+
+        get_pet_structs(name: Option<String>) -> [Pet] {
+            "SELECT id, name, data FROM pet WHERE pet.name = :name"
+        }
+
+        insert_new_pet(name: String, data: Option<Vec<u8>>) {
+            "INSERT INTO pet (name, data) VALUES (:name, :data)"
+        }
+
+        /// Same as `insert_new_pet`, but takes the name as `&str`.
+        #[many]
+        insert_new_pet_str(name: str, data: Option<Vec<u8>>) {
+            "INSERT INTO pet (name, data) VALUES (:name, :data)"
+        }
+
+        /// Inserts a pet, binding its fields to the placeholders named after them.
+        #[many]
+        insert_pet(pet: &Pet { id, name, data }) {
+            "INSERT INTO pet (id, name, data) VALUES (:id, :name, :data)"
+        }
+
+        update_pet_data(name: str, data: [u8]) {
+            "UPDATE pet SET data = :data WHERE name = :name"
+        }
+
+        /// Inserts a pet, returning its id.
+        #[rowid = PetId]
+        insert_named_pet(name: str) {
+            "INSERT INTO pet (name) VALUES (:name)"
+        }
+
+        insert_pet_returning(name: str) -> [(id: i32, name: String)] {
+            "INSERT INTO pet (name) VALUES (:name) RETURNING id, name"
+        }
+
+        clear_pet_data(ids: [i32] as list) -> [(i32)] {
+            "UPDATE pet SET data = NULL WHERE id IN (:ids) RETURNING id"
+        }
+
+        #[expect_rows = 1]
+        delete_pet(id: i32) {
+            "DELETE FROM pet WHERE id = :id"
+        }
+
+        get_pet_by_id(id: i32) -> (String, Option<Vec<u8>>) {
+            "SELECT name, data FROM pet WHERE id = :id"
+        }
+
+        count_pets(name: Option<String>) -> i64 {
+            "SELECT count(*) FROM pet WHERE 1 = 1 [AND name = :name]"
+        }
+
+        get_pet_names_by_ids(ids: [i32] as list) -> [(String)] {
+            "SELECT name FROM pet WHERE id IN (:ids) ORDER BY id"
+        }
+
+        find_existing_ids(ids: [i32] as list) -> [(i32)] {
+            "SELECT id FROM pet WHERE id IN (:ids)"
+        }
+
+        find_ids_or_name(ids: [i32] as list, name: str) -> [(i32)] {
+            "SELECT id FROM pet WHERE id IN (:ids) OR name = :name"
+        }
+
+        count_pets_by_ids(ids: [i32] as list) -> i64 {
+            "SELECT count(*) FROM pet WHERE id IN (:ids)"
+        }
+
+        delete_pets_except(name: str, ids: [i32] as list) {
+            "DELETE FROM pet WHERE name = :name AND id NOT IN (:ids)"
+        }
+
+        get_pet_name_structs() -> [PetName] {
+            "SELECT name FROM pet ORDER BY id"
+        }
+
+        find_pets(name: Option<String>, min_id: Option<i32>) -> [Pet] {
+            "SELECT id, name, data FROM pet WHERE 1 = 1 [AND name = :name] [AND id >= :min_id]"
+        }
+
+        #[update]
+        update_pet(id: i32, name: Option<String>, data: Option<Option<Vec<u8>>>) {
+            "UPDATE pet SET name = :name, data = :data WHERE id = :id"
+        }
+
+        /// Counts the pets having the given id.
+        #[deprecated(note = "ids are unique")]
+        get_pet_count(pet_id: i64) -> i64 {r#"
+             SELECT count(*)
+               FROM pet
+              WHERE id = :pet_id
+        "#}
+    }
+}
+
+#[derive(Debug, fnsql::FromRow)]
+#[fnsql(rusqlite)]
+#[cfg_attr(test, derive(arbitrary::Arbitrary))]
+pub struct Pet {
+    id: i32,
+    name: String,
+    data: Option<Vec<u8>>,
+}
+
+/// A non-empty pet name, whose `FromRow` fails with an error of its own.
+#[derive(Debug)]
+pub struct PetName(String);
+
+impl<'a> fnsql::FromRow<rusqlite::Row<'a>> for PetName {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_row(row: &rusqlite::Row<'a>) -> Result<Self, Self::Error> {
+        let name: String = row.get(0)?;
+        if name.is_empty() {
+            return Err("empty pet name".into());
+        }
+        Ok(PetName(name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PetId(pub i64);
+
+impl From<i64> for PetId {
+    fn from(id: i64) -> Self {
+        PetId(id)
+    }
+}
+
+use pets::Queries;
+
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut conn = rusqlite::Connection::open_in_memory()?;
+    println!("SQLite {}", conn.query_scalar_sqlite_version()?);
+
+    {
+        conn.execute_batch_create_table_pet()?;
+        let me = Pet {
+            id: 0,
+            name: "Max".to_string(),
+            data: None,
+        };
+        conn.execute_insert_new_pet(&me.name, &me.data)?;
+        conn.execute_insert_pet(&Pet { id: 100, name: me.name.clone(), data: None })?;
+        {
+            let mut stmt = conn.prepare_get_pet_id_data()?;
+            let pet_iter = stmt.query_map(&Some("Max".to_string()), |id, data| {
+                Ok::<_, rusqlite::Error>(Pet {
+                    id,
+                    data,
+                    name: "Max".to_string(),
+                })
+            })?;
+
+            for pet in pet_iter {
+                println!("Found pet {:?}", pet.unwrap());
+            }
+
+            for pet in stmt.query(&Some("Max".to_string()))? {
+                let pet = pet?;
+                println!("Found pet {:?}", pet);
+            }
+
+            {
+                let mut rows = stmt.query(&Some("Max".to_string()))?;
+                while let Some(Ok(pet)) = rows.next() {
+                    println!("Found pet {:?}", pet);
+                }
+            }
+        }
+        {
+            let mut stmt = conn.prepare_cached_get_pet_id_data()?;
+            let _pet_iter = stmt.query_map(&Some("Max".to_string()), |id, data| {
+                Ok::<_, rusqlite::Error>(Pet {
+                    id,
+                    data,
+                    name: "Max".to_string(),
+                })
+            })?;
+        }
+        conn.execute_insert_new_pet_str(&me.name, &me.data)?;
+        let count = conn.insert_many_insert_new_pet_str(vec![("Luna", None), ("Max", Some(vec![1]))])?;
+        println!("Inserted {} pets", count);
+        let pets: Vec<_> = (300..303).map(|id| Pet { id, name: format!("Pet {}", id), data: None }).collect();
+        println!("Inserted {} pets", conn.insert_many_insert_pet(&pets)?);
+        let duplicates: Vec<_> = (0..2).map(|_| Pet { id: 400, name: "Rex".to_string(), data: None }).collect();
+        assert!(conn.insert_many_insert_pet(&duplicates).is_err());
+        assert!(conn.query_one_get_pet_by_id(&400).is_err());
+
+        {
+            let mut stmt = conn.prepare_insert_new_pet_str()?;
+            stmt.execute(&me.name, &me.data)?;
+        }
+
+        {
+            let mut stmt = conn.prepare_cached_insert_new_pet_str()?;
+            stmt.execute(&me.name, &me.data)?;
+        }
+
+        let _pet: Pet =
+            conn.query_row_get_pet_id_data(&Some("Max".to_string()), |id, data| Pet {
+                id,
+                data,
+                name: "Max".to_string(),
+            })?;
+    }
+
+    {
+        conn.execute_update_pet_data("x", "asd".as_bytes())?;
+    }
+
+    {
+        let mut stmt = conn.prepare_get_pets()?;
+        for pet in stmt.query(&Some("Max".to_string()))? {
+            let pet = pet?;
+            println!("Found pet {} {:?} {:?}", pet.id, pet.name, pet.data);
+        }
+
+        let names = stmt.query_map(&Some("Max".to_string()), |row| {
+            Ok::<_, rusqlite::Error>(row.name)
+        })?;
+        for name in names {
+            println!("Found pet name {:?}", name?);
+        }
+    }
+
+    {
+        let mut stmt = conn.prepare_get_pet_structs()?;
+        for pet in stmt.query(&Some("Max".to_string()))? {
+            let pet: Pet = pet?;
+            println!("Found pet {} {:?}", pet.id, pet);
+        }
+    }
+
+    {
+        let mut stmt = conn.prepare_get_pet_name_structs()?;
+        for name in stmt.query()? {
+            println!("Found pet name {}", name?.0);
+        }
+        conn.execute_insert_new_pet(&String::new(), &None)?;
+        let names = stmt.query()?.collect::<Result<Vec<_>, _>>();
+        assert!(matches!(names, Err(rusqlite::Error::UserFunctionError(..))));
+    }
+
+    {
+        // Longer than the SQLite limit on placeholders, so it is split where that
+        // gives the same result, and otherwise bound at once
+        let ids: Vec<i32> = (0..2000).collect();
+        let names = conn.query_get_pet_names_by_ids(&ids)?;
+        println!("Found pet names {:?}", names);
+        let count = conn.query_scalar_count_pets(&None)?;
+        assert_eq!(conn.query_find_existing_ids(&ids)?.len() as i64, count);
+        assert_eq!(conn.query_scalar_count_pets_by_ids(&ids)?, count);
+        let mut found = conn.query_find_ids_or_name(&ids, "Max")?;
+        let all = found.len();
+        found.sort_unstable();
+        found.dedup();
+        assert_eq!(found.len(), all);
+        assert_eq!(conn.execute_delete_pets_except("Max", &ids)?, 0);
+
+        let mut stmt = conn.prepare_cached_get_pet_names_by_ids(2)?;
+        for name in stmt.query(&[1, 2])? {
+            println!("Found pet name {:?}", name?);
+        }
+
+        let deleted = conn.execute_delete_pets_except("Max", &[1])?;
+        println!("Deleted {} pets", deleted);
+    }
+
+    {
+        for pet in conn.query_find_pets(&None, &Some(1))? {
+            println!("Found pet {:?}", pet);
+        }
+        let pet = conn.query_row_find_pets(&Some("Max".to_string()), &None, |pet: Pet| pet.id)?;
+        println!("Found pet {}", pet);
+    }
+
+    {
+        let (name, data) = conn.query_one_get_pet_by_id(&1)?;
+        println!("Found pet {:?} {:?}", name, data);
+        assert!(matches!(conn.query_one_get_pet_by_id(&-1), Err(fnsql::Error::NoRows)));
+        println!("Found {:?} pets", conn.query_scalar_count_pets(&None)?);
+    }
+
+    {
+        let id = conn.execute_rowid_insert_named_pet("Luna")?;
+        println!("Inserted pet {:?}", id);
+        let id = conn.prepare_cached_insert_named_pet()?.insert("Luna")?;
+        println!("Inserted pet {:?}", id);
+
+        for pet in conn.execute_insert_pet_returning("Charlie")? {
+            println!("Inserted pet {} {:?}", pet.id, pet.name);
+        }
+        println!("Cleared pets {:?}", conn.execute_clear_pet_data(&[id.0 as i32])?);
+    }
+
+    {
+        conn.execute_insert_pet(&Pet { id: 200, name: "Bella".to_string(), data: None })?;
+        conn.execute_delete_pet(&200)?;
+        assert!(matches!(conn.prepare_delete_pet()?.execute(&200),
+            Err(fnsql::Error::AffectedRows { count: 0, .. })));
+    }
+
+    {
+        let updated = conn.execute_update_pet(&1, &None, &Some(Some(vec![1, 2])))?;
+        assert_eq!(updated, 1);
+        assert!(matches!(conn.execute_update_pet(&1, &None, &None),
+            Err(fnsql::Error::NoAssignments)));
+    }
+
+    let tx = conn.transaction()?;
+
+    {
+        let mut stmt = tx.prepare_cached_get_pet_id_data()?;
+        {
+            let _pet_iter = stmt.query_map(&Some("Max".to_string()), |id, data| {
+                Ok::<_, rusqlite::Error>(Pet {
+                    id,
+                    data,
+                    name: "Max".to_string(),
+                })
+            })?;
+        }
+        let _pet: Pet = stmt.query_row(&Some("Max".to_string()), |id, data| Pet {
+            id,
+            data,
+            name: "Max".to_string(),
+        })?;
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}